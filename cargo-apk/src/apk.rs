@@ -1,14 +1,16 @@
 use crate::error::Error;
 use crate::manifest::{Inheritable, Manifest, Root};
+use crate::validate::{validate_manifest, validate_package_name};
 use cargo_subcommand::{Artifact, ArtifactType, CrateType, Profile, Subcommand};
-use ndk_build::apk::{Apk, ApkConfig};
+use ndk_build::apk::{ActivityBackend, Apk, ApkConfig, StripConfig};
 use ndk_build::cargo::{cargo_ndk, VersionCode};
 use ndk_build::dylibs::get_libs_search_paths;
 use ndk_build::error::NdkError;
 use ndk_build::manifest::{IntentFilter, MetaData};
 use ndk_build::ndk::{Key, Ndk};
 use ndk_build::target::Target;
-use std::path::PathBuf;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
 
 pub struct ApkBuilder<'a> {
     cmd: &'a Subcommand,
@@ -17,12 +19,14 @@ pub struct ApkBuilder<'a> {
     build_dir: PathBuf,
     build_targets: Vec<Target>,
     device_serial: Option<String>,
+    variant: Option<String>,
 }
 
 impl<'a> ApkBuilder<'a> {
     pub fn from_subcommand(
         cmd: &'a Subcommand,
         device_serial: Option<String>,
+        variant: Option<String>,
     ) -> Result<Self, Error> {
         println!(
             "Using package `{}` in `{}`",
@@ -30,11 +34,17 @@ impl<'a> ApkBuilder<'a> {
             cmd.manifest().display()
         );
         let ndk = Ndk::from_env()?;
-        let mut manifest = Manifest::parse_from_toml(cmd.manifest())?;
         let workspace_manifest: Option<Root> = cmd
             .workspace_manifest()
             .map(Root::parse_from_toml)
             .transpose()?;
+        let mut manifest = Manifest::parse_from_toml(
+            cmd.manifest(),
+            cmd.workspace_manifest().zip(workspace_manifest.as_ref()),
+            cmd.profile(),
+            variant.as_deref(),
+            ndk.default_target_platform(),
+        )?;
         let build_targets = if let Some(target) = cmd.target() {
             vec![Target::from_rust_triple(target)?]
         } else if !manifest.build_targets.is_empty() {
@@ -44,9 +54,12 @@ impl<'a> ApkBuilder<'a> {
                 .detect_abi(device_serial.as_deref())
                 .unwrap_or(Target::Arm64V8a)]
         };
-        let build_dir = dunce::simplified(cmd.target_dir())
+        let mut build_dir = dunce::simplified(cmd.target_dir())
             .join(cmd.profile())
             .join("apk");
+        if let Some(variant) = &variant {
+            build_dir = build_dir.join(variant);
+        }
 
         let package_version = match &manifest.version {
             Inheritable::Value(v) => v.clone(),
@@ -80,7 +93,7 @@ impl<'a> ApkBuilder<'a> {
             .replace(package_version)
             .is_some()
         {
-            panic!("version_name should not be set in TOML");
+            return Err(Error::ReservedManifestField("version_name"));
         }
 
         if manifest
@@ -89,7 +102,7 @@ impl<'a> ApkBuilder<'a> {
             .replace(version_code)
             .is_some()
         {
-            panic!("version_code should not be set in TOML");
+            return Err(Error::ReservedManifestField("version_code"));
         }
 
         let target_sdk_version = *manifest
@@ -97,6 +110,7 @@ impl<'a> ApkBuilder<'a> {
             .sdk
             .target_sdk_version
             .get_or_insert_with(|| ndk.default_target_platform());
+        validate_manifest(&manifest.android_manifest)?;
 
         manifest
             .android_manifest
@@ -104,26 +118,41 @@ impl<'a> ApkBuilder<'a> {
             .debuggable
             .get_or_insert_with(|| *cmd.profile() == Profile::Dev);
 
-        let activity = &mut manifest.android_manifest.application.activity;
+        let activity_backend = manifest.activity_backend;
+        if let Some(theme) = activity_backend.theme() {
+            manifest
+                .android_manifest
+                .application
+                .theme
+                .get_or_insert_with(|| theme.to_string());
+        }
 
-        // Add a default `MAIN` action to launch the activity, if the user didn't supply it by hand.
-        if activity
-            .intent_filter
-            .iter()
-            .all(|i| i.actions.iter().all(|f| f != "android.intent.action.MAIN"))
+        if let Some(activity) = manifest
+            .android_manifest
+            .application
+            .launcher_activity_mut()
         {
-            activity.intent_filter.push(IntentFilter {
-                actions: vec!["android.intent.action.MAIN".to_string()],
-                categories: vec!["android.intent.category.LAUNCHER".to_string()],
-                data: vec![],
-            });
-        }
+            // Host the native code in the activity of the selected backend, unless the user
+            // picked another class, e.g. one deriving from `GameActivity`.
+            if activity.name == ActivityBackend::NativeActivity.activity_name() {
+                activity.name = activity_backend.activity_name().to_string();
+            }
 
-        // Export the sole Rust activity on Android S and up, if the user didn't explicitly do so.
-        // Without this, apps won't start on S+.
-        // https://developer.android.com/about/versions/12/behavior-changes-12#exported
-        if target_sdk_version >= 31 {
-            activity.exported.get_or_insert(true);
+            // Add a default `MAIN` action to launch the activity, if the user didn't supply it by hand.
+            if !activity.has_main_action() {
+                activity.intent_filter.push(IntentFilter {
+                    actions: vec!["android.intent.action.MAIN".to_string()],
+                    categories: vec!["android.intent.category.LAUNCHER".to_string()],
+                    data: vec![],
+                });
+            }
+
+            // Export the launcher activity on Android S and up, if the user didn't explicitly do so.
+            // Without this, apps won't start on S+.
+            // https://developer.android.com/about/versions/12/behavior-changes-12#exported
+            if target_sdk_version >= 31 {
+                activity.exported.get_or_insert(true);
+            }
         }
 
         Ok(Self {
@@ -133,6 +162,7 @@ impl<'a> ApkBuilder<'a> {
             build_dir,
             build_targets,
             device_serial,
+            variant,
         })
     }
 
@@ -143,6 +173,7 @@ impl<'a> ApkBuilder<'a> {
                 *target,
                 self.min_sdk_version(),
                 self.cmd.target_dir(),
+                self.manifest.page_size_16k,
             )?;
             cargo.arg("check");
             if self.cmd.target().is_none() {
@@ -157,8 +188,9 @@ impl<'a> ApkBuilder<'a> {
         Ok(())
     }
 
-    pub fn build(&self, artifact: &Artifact) -> Result<Apk, Error> {
-        // Set artifact specific manifest default values.
+    /// Creates the [`ApkConfig`] for `artifact`, filling in artifact specific manifest
+    /// default values
+    fn apk_config(&self, artifact: &Artifact) -> Result<ApkConfig, Error> {
         let mut manifest = self.manifest.android_manifest.clone();
 
         if manifest.package.is_empty() {
@@ -169,19 +201,25 @@ impl<'a> ApkBuilder<'a> {
                 ArtifactType::Example => format!("rust.example.{name}"),
             };
         }
+        validate_package_name(&manifest.package)?;
 
         if manifest.application.label.is_empty() {
             manifest.application.label = artifact.name.to_string();
         }
 
-        manifest.application.activity.meta_data.push(MetaData {
-            name: "android.app.lib_name".to_string(),
-            value: artifact.name.replace('-', "_"),
-        });
+        if let Some(activity) = manifest.application.launcher_activity_mut() {
+            activity.meta_data.push(MetaData {
+                name: "android.app.lib_name".to_string(),
+                value: artifact.name.replace('-', "_"),
+                resource: None,
+            });
+        }
 
-        let crate_path = self.cmd.manifest().parent().expect("invalid manifest path");
+        let crate_path = self.crate_path();
 
-        let is_debug_profile = *self.cmd.profile() == Profile::Dev;
+        for overlay in &mut manifest.overlays {
+            *overlay = dunce::simplified(&crate_path.join(&*overlay)).to_owned();
+        }
 
         let assets = self
             .manifest
@@ -193,30 +231,76 @@ impl<'a> ApkBuilder<'a> {
             .resources
             .as_ref()
             .map(|res| dunce::simplified(&crate_path.join(res)).to_owned());
-        let runtime_libs = self
+        let java_sources = self
             .manifest
-            .runtime_libs
+            .java_sources
             .as_ref()
-            .map(|libs| dunce::simplified(&crate_path.join(libs)).to_owned());
-        let apk_name = self
+            .map(|src| dunce::simplified(&crate_path.join(src)).to_owned());
+        let mut jars = self
+            .manifest
+            .jars
+            .iter()
+            .map(|jar| dunce::simplified(&crate_path.join(jar)).to_owned())
+            .collect::<Vec<_>>();
+        let mut aars = self
+            .manifest
+            .aars
+            .iter()
+            .map(|aar| dunce::simplified(&crate_path.join(aar)).to_owned())
+            .collect::<Vec<_>>();
+        for library in self
+            .manifest
+            .activity_backend
+            .libraries(&self.ndk, self.manifest.game_activity_version.as_deref())?
+        {
+            if library.extension() == Some(OsStr::new("aar")) {
+                aars.push(library);
+            } else {
+                jars.push(library);
+            }
+        }
+        let mut apk_name = self
             .manifest
             .apk_name
             .clone()
             .unwrap_or_else(|| artifact.name.to_string());
+        if let Some(variant) = &self.variant {
+            apk_name = format!("{apk_name}-{variant}");
+        }
 
-        let config = ApkConfig {
+        if java_sources.is_some() || !jars.is_empty() || !aars.is_empty() {
+            manifest.application.has_code = true;
+        }
+
+        Ok(ApkConfig {
             ndk: self.ndk.clone(),
             build_dir: self.build_dir.join(artifact.build_dir()),
             apk_name,
             assets,
             resources,
             manifest,
-            disable_aapt_compression: is_debug_profile,
+            java_sources,
+            jars,
+            aars,
+            disable_aapt_compression: self.is_debug_profile(),
             strip: self.manifest.strip,
+            signer: self.manifest.signer,
+            split_abis: self.manifest.split_abis,
+            reproducible: self.manifest.reproducible,
+            missing_libs: self.manifest.missing_libs,
+            allowed_missing_libs: self.manifest.allowed_missing_libs.clone(),
+            page_size_16k: self.manifest.page_size_16k,
             reverse_port_forward: self.manifest.reverse_port_forward.clone(),
-        };
-        let mut apk = config.create_apk()?;
+        })
+    }
 
+    /// Builds `artifact` for every target and passes the resulting library, together
+    /// with the paths to search for its dependencies, to `add_libs`
+    fn build_libs(
+        &self,
+        artifact: &Artifact,
+        mut add_libs: impl FnMut(Target, &Path, &[&Path]) -> Result<(), NdkError>,
+    ) -> Result<(), Error> {
         for target in &self.build_targets {
             let triple = target.rust_triple();
             let build_dir = self.cmd.build_dir(Some(triple));
@@ -227,6 +311,7 @@ impl<'a> ApkBuilder<'a> {
                 *target,
                 self.min_sdk_version(),
                 self.cmd.target_dir(),
+                self.manifest.page_size_16k,
             )?;
             cargo.arg("build");
             if self.cmd.target().is_none() {
@@ -247,13 +332,14 @@ impl<'a> ApkBuilder<'a> {
                 .map(|path| path.as_path())
                 .collect::<Vec<_>>();
 
-            apk.add_lib_recursively(&artifact, *target, libs_search_paths.as_slice())?;
-
-            if let Some(runtime_libs) = &runtime_libs {
-                apk.add_runtime_libs(runtime_libs, *target, libs_search_paths.as_slice())?;
-            }
+            add_libs(*target, &artifact, libs_search_paths.as_slice())?;
         }
+        Ok(())
+    }
 
+    /// Selects the key to sign with for the current profile, from the environment,
+    /// the manifest or the default debug keystore
+    fn signing_key(&self) -> Result<Key, Error> {
         let profile_name = match self.cmd.profile() {
             Profile::Dev => "dev",
             Profile::Release => "release",
@@ -270,12 +356,17 @@ impl<'a> ApkBuilder<'a> {
         let password = std::env::var(&password_env).ok();
 
         let signing_key = match (path, password) {
-            (Some(path), Some(password)) => Key { path, password },
-            (Some(path), None) if is_debug_profile => {
+            (Some(path), Some(password)) => Key {
+                path,
+                password,
+                certificate: None,
+            },
+            (Some(path), None) if self.is_debug_profile() => {
                 eprintln!("{password_env} not specified, falling back to default password");
                 Key {
                     path,
                     password: ndk_build::ndk::DEFAULT_DEV_KEYSTORE_PASSWORD.to_owned(),
+                    certificate: None,
                 }
             }
             (Some(path), None) => {
@@ -284,19 +375,59 @@ impl<'a> ApkBuilder<'a> {
             }
             (None, _) => {
                 if let Some(msk) = self.manifest.signing.get(profile_name) {
+                    let crate_path = self.crate_path();
                     Key {
                         path: crate_path.join(&msk.path),
                         password: msk.keystore_password.clone(),
+                        certificate: msk.certificate.as_ref().map(|c| crate_path.join(c)),
                     }
-                } else if is_debug_profile {
+                } else if self.is_debug_profile() {
                     self.ndk.debug_key()?
                 } else {
                     return Err(Error::MissingReleaseKey(profile_name.to_owned()));
                 }
             }
         };
+        Ok(signing_key)
+    }
+
+    fn runtime_libs(&self) -> Option<PathBuf> {
+        self.manifest
+            .runtime_libs
+            .as_ref()
+            .map(|libs| dunce::simplified(&self.crate_path().join(libs)).to_owned())
+    }
 
+    fn crate_path(&self) -> &Path {
+        self.cmd.manifest().parent().expect("invalid manifest path")
+    }
+
+    fn is_debug_profile(&self) -> bool {
+        *self.cmd.profile() == Profile::Dev
+    }
+
+    pub fn build(&self, artifact: &Artifact) -> Result<Apk, Error> {
+        let config = self.apk_config(artifact)?;
+        let mut apk = config.create_apk()?;
+
+        let runtime_libs = self.runtime_libs();
+        self.build_libs(artifact, |target, lib, search_paths| {
+            apk.add_lib_recursively(lib, target, search_paths)?;
+            apk.add_aar_libs(target, search_paths)?;
+            if let Some(runtime_libs) = &runtime_libs {
+                apk.add_runtime_libs(runtime_libs, target, search_paths)?;
+            }
+            Ok(())
+        })?;
+
+        let signing_key = self.signing_key()?;
         let unsigned = apk.add_pending_libs_and_align()?;
+        if config.strip != StripConfig::Default {
+            println!(
+                "Wrote native debug symbols to `{}`",
+                config.native_debug_symbols().display()
+            );
+        }
 
         println!(
             "Signing `{}` with keystore `{}`",
@@ -306,11 +437,52 @@ impl<'a> ApkBuilder<'a> {
         Ok(unsigned.sign(signing_key)?)
     }
 
-    pub fn run(&self, artifact: &Artifact, no_logcat: bool) -> Result<(), Error> {
+    /// Builds a signed Android App Bundle (`.aab`) for `artifact`, for upload to
+    /// Google Play
+    pub fn bundle(&self, artifact: &Artifact) -> Result<PathBuf, Error> {
+        let config = self.apk_config(artifact)?;
+        let mut bundle = config.create_bundle()?;
+
+        let runtime_libs = self.runtime_libs();
+        self.build_libs(artifact, |target, lib, search_paths| {
+            bundle.add_lib_recursively(lib, target, search_paths)?;
+            bundle.add_aar_libs(target, search_paths)?;
+            if let Some(runtime_libs) = &runtime_libs {
+                bundle.add_runtime_libs(runtime_libs, target, search_paths)?;
+            }
+            Ok(())
+        })?;
+
+        let signing_key = self.signing_key()?;
+        let unsigned = bundle.add_pending_libs()?;
+        if config.strip != StripConfig::Default {
+            println!(
+                "Wrote native debug symbols to `{}`",
+                config.native_debug_symbols().display()
+            );
+        }
+
+        println!(
+            "Signing `{}` with keystore `{}`",
+            config.bundle().display(),
+            signing_key.path.display()
+        );
+        Ok(unsigned.sign(signing_key)?)
+    }
+
+    /// Builds, installs and starts `activity`, or the launcher activity when `None`, passing
+    /// `intent_args` on to `am start`
+    pub fn run(
+        &self,
+        artifact: &Artifact,
+        no_logcat: bool,
+        activity: Option<&str>,
+        intent_args: &[String],
+    ) -> Result<(), Error> {
         let apk = self.build(artifact)?;
         apk.reverse_port_forwarding(self.device_serial.as_deref())?;
         apk.install(self.device_serial.as_deref())?;
-        apk.start(self.device_serial.as_deref())?;
+        apk.start_activity(self.device_serial.as_deref(), activity, intent_args)?;
         let uid = apk.uidof(self.device_serial.as_deref())?;
 
         if !no_logcat {
@@ -331,12 +503,12 @@ impl<'a> ApkBuilder<'a> {
         let apk = self.build(artifact)?;
         apk.install(self.device_serial.as_deref())?;
 
+        let activity = apk
+            .launcher_activity()
+            .ok_or(NdkError::NoLauncherActivity)?;
         let target_dir = self.build_dir.join(artifact.build_dir());
-        self.ndk.ndk_gdb(
-            target_dir,
-            "android.app.NativeActivity",
-            self.device_serial.as_deref(),
-        )?;
+        self.ndk
+            .ndk_gdb(target_dir, activity, self.device_serial.as_deref())?;
         Ok(())
     }
 
@@ -347,6 +519,7 @@ impl<'a> ApkBuilder<'a> {
                 *target,
                 self.min_sdk_version(),
                 self.cmd.target_dir(),
+                self.manifest.page_size_16k,
             )?;
             cargo.arg(cargo_cmd);
             self.cmd.args().apply(&mut cargo);
@@ -8,7 +8,7 @@ use toml::de::Error as TomlError;
 pub enum Error {
     #[error(transparent)]
     Subcommand(#[from] SubcommandError),
-    #[error("Failed to parse config.")]
+    #[error("Failed to parse config: {0}")]
     Config(#[from] TomlError),
     #[error(transparent)]
     Ndk(#[from] NdkError),
@@ -22,6 +22,26 @@ pub enum Error {
     InheritanceMissingWorkspace,
     #[error("Failed to inherit field: `workspace.{0}` was not defined in workspace root manifest")]
     WorkspaceMissingInheritedField(&'static str),
+    #[error("Variant `{0}` is not defined in `[package.metadata.android.variants]`, available variants: {1:?}")]
+    UnknownVariant(String, Vec<String>),
+    #[error("Unresolved placeholder `{placeholder}` in `{field}`")]
+    UnresolvedPlaceholder { field: String, placeholder: String },
+    #[error("Invalid `[package.metadata.android]`:\n{}", .0.join("\n"))]
+    InvalidMetadata(Vec<String>),
+    #[error(
+        "`{0}` is set from the package version and must not be set in `[package.metadata.android]`"
+    )]
+    ReservedManifestField(&'static str),
+    #[error("`{0}` is not a valid package name, which requires at least two `.`-separated segments that start with a letter and contain only letters, digits and `_`")]
+    InvalidPackageName(String),
+    #[error("SDK versions must satisfy `min_sdk_version <= target_sdk_version <= max_sdk_version`, got min {min:?}, target {target:?} and max {max:?}")]
+    InconsistentSdkVersions {
+        min: Option<u32>,
+        target: Option<u32>,
+        max: Option<u32>,
+    },
+    #[error("Permission `{0}` is requested more than once in `uses_permission`")]
+    DuplicatePermission(String),
 }
 
 impl Error {
@@ -1,6 +1,10 @@
 mod apk;
 mod error;
 mod manifest;
+mod placeholders;
+mod schema;
+mod validate;
 
 pub use apk::ApkBuilder;
 pub use error::Error;
+pub use schema::schema;
@@ -27,6 +27,9 @@ struct Args {
     /// Use device with the given serial (see `adb devices`)
     #[clap(short, long)]
     device: Option<String>,
+    /// Build the variant defined in `[package.metadata.android.variants.<VARIANT>]`
+    #[clap(long)]
+    variant: Option<String>,
 }
 
 #[derive(clap::Subcommand)]
@@ -43,6 +46,11 @@ enum ApkSubCmd {
         #[clap(flatten)]
         args: Args,
     },
+    /// Compile the current package and create an Android App Bundle (`.aab`)
+    Bundle {
+        #[clap(flatten)]
+        args: Args,
+    },
     /// Invoke `cargo` under the detected NDK environment
     #[clap(name = "--")]
     Ndk {
@@ -64,12 +72,22 @@ enum ApkSubCmd {
         /// Do not print or follow `logcat` after running the app
         #[clap(short, long)]
         no_logcat: bool,
+        /// Start the given activity instead of the launcher activity
+        #[clap(long)]
+        activity: Option<String>,
+        /// Arguments passed on to `am start` after `--` to add extras and data to the intent,
+        /// e.g. `-- --es key value -d https://example.com`
+        #[clap(last = true)]
+        intent_args: Vec<String>,
     },
     /// Start a gdb session attached to an adb device with symbols loaded
     Gdb {
         #[clap(flatten)]
         args: Args,
     },
+    /// Print the JSON Schema of `[package.metadata.android]`, for editor completion and
+    /// validation
+    Schema,
     /// Print the version of cargo-apk
     Version,
 }
@@ -146,16 +164,23 @@ fn main() -> anyhow::Result<()> {
     match cmd {
         ApkSubCmd::Check { args } => {
             let cmd = Subcommand::new(args.subcommand_args)?;
-            let builder = ApkBuilder::from_subcommand(&cmd, args.device)?;
+            let builder = ApkBuilder::from_subcommand(&cmd, args.device, args.variant)?;
             builder.check()?;
         }
         ApkSubCmd::Build { args } => {
             let cmd = Subcommand::new(args.subcommand_args)?;
-            let builder = ApkBuilder::from_subcommand(&cmd, args.device)?;
+            let builder = ApkBuilder::from_subcommand(&cmd, args.device, args.variant)?;
             for artifact in cmd.artifacts() {
                 builder.build(artifact)?;
             }
         }
+        ApkSubCmd::Bundle { args } => {
+            let cmd = Subcommand::new(args.subcommand_args)?;
+            let builder = ApkBuilder::from_subcommand(&cmd, args.device, args.variant)?;
+            for artifact in cmd.artifacts() {
+                builder.bundle(artifact)?;
+            }
+        }
         ApkSubCmd::Ndk {
             cargo_cmd,
             cargo_args,
@@ -163,21 +188,29 @@ fn main() -> anyhow::Result<()> {
             let (args, cargo_args) = split_apk_and_cargo_args(cargo_args);
 
             let cmd = Subcommand::new(args.subcommand_args)?;
-            let builder = ApkBuilder::from_subcommand(&cmd, args.device)?;
+            let builder = ApkBuilder::from_subcommand(&cmd, args.device, args.variant)?;
             builder.default(&cargo_cmd, &cargo_args)?;
         }
-        ApkSubCmd::Run { args, no_logcat } => {
+        ApkSubCmd::Run {
+            args,
+            no_logcat,
+            activity,
+            intent_args,
+        } => {
             let cmd = Subcommand::new(args.subcommand_args)?;
-            let builder = ApkBuilder::from_subcommand(&cmd, args.device)?;
+            let builder = ApkBuilder::from_subcommand(&cmd, args.device, args.variant)?;
             let artifact = iterator_single_item(cmd.artifacts()).ok_or(Error::invalid_args())?;
-            builder.run(artifact, no_logcat)?;
+            builder.run(artifact, no_logcat, activity.as_deref(), &intent_args)?;
         }
         ApkSubCmd::Gdb { args } => {
             let cmd = Subcommand::new(args.subcommand_args)?;
-            let builder = ApkBuilder::from_subcommand(&cmd, args.device)?;
+            let builder = ApkBuilder::from_subcommand(&cmd, args.device, args.variant)?;
             let artifact = iterator_single_item(cmd.artifacts()).ok_or(Error::invalid_args())?;
             builder.gdb(artifact)?;
         }
+        ApkSubCmd::Schema => {
+            println!("{}", cargo_apk::schema());
+        }
         ApkSubCmd::Version => {
             println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
         }
@@ -302,6 +335,7 @@ fn test_split_apk_and_cargo_args() {
                     ..args_default.subcommand_args
                 },
                 device: Some("adb:test".to_string()),
+                variant: None,
             },
             vec!["--no-deps".to_string(), "--unrecognized".to_string()]
         )
@@ -1,7 +1,11 @@
 use crate::error::Error;
-use ndk_build::apk::StripConfig;
+use crate::placeholders::Placeholders;
+use crate::validate::{validate_metadata, Sources};
+use cargo_subcommand::Profile;
+use ndk_build::apk::{ActivityBackend, MissingLibs, SignerBackend, StripConfig};
 use ndk_build::manifest::AndroidManifest;
 use ndk_build::target::Target;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use std::{
     collections::HashMap,
@@ -23,25 +27,72 @@ pub(crate) struct Manifest {
     pub(crate) assets: Option<PathBuf>,
     pub(crate) resources: Option<PathBuf>,
     pub(crate) runtime_libs: Option<PathBuf>,
+    pub(crate) java_sources: Option<PathBuf>,
+    pub(crate) jars: Vec<PathBuf>,
+    pub(crate) aars: Vec<PathBuf>,
+    pub(crate) activity_backend: ActivityBackend,
+    pub(crate) game_activity_version: Option<String>,
+    pub(crate) split_abis: bool,
+    pub(crate) reproducible: bool,
     /// Maps profiles to keystores
     pub(crate) signing: HashMap<String, Signing>,
+    pub(crate) signer: SignerBackend,
     pub(crate) reverse_port_forward: HashMap<String, String>,
     pub(crate) strip: StripConfig,
+    pub(crate) missing_libs: MissingLibs,
+    pub(crate) allowed_missing_libs: Vec<String>,
+    pub(crate) page_size_16k: bool,
 }
 
 impl Manifest {
-    pub(crate) fn parse_from_toml(path: &Path) -> Result<Self, Error> {
+    /// Parses the `[package.metadata.android]` table of the manifest at `path`, merged over
+    /// the table from [`Root::workspace_android_metadata()`] of the `workspace` manifest, with the
+    /// `[package.metadata.android.variants.<variant>]` table merged over it, followed by
+    /// the `[package.metadata.android.profile.<profile>]` table, and expands the
+    /// [`Placeholders`] in its string fields. `${target_sdk}` expands to
+    /// `default_target_sdk` unless `sdk.target_sdk_version` is set. The result is checked
+    /// with [`validate_metadata()`].
+    pub(crate) fn parse_from_toml(
+        path: &Path,
+        workspace: Option<(&Path, &Root)>,
+        profile: &Profile,
+        variant: Option<&str>,
+        default_target_sdk: u32,
+    ) -> Result<Self, Error> {
         let toml = Root::parse_from_toml(path)?;
         // Unlikely to fail as cargo-subcommand should give us a `Cargo.toml` containing
         // a `[package]` table (with a matching `name` when requested by the user)
         let package = toml
             .package
             .unwrap_or_else(|| panic!("Manifest `{:?}` must contain a `[package]`", path));
-        let metadata = package
-            .metadata
-            .unwrap_or_default()
-            .android
-            .unwrap_or_default();
+        let mut metadata = workspace
+            .and_then(|(path, root)| root.workspace_android_metadata(path.parent()?))
+            .unwrap_or_else(|| toml::Value::Table(Default::default()));
+        if let Some(android) = package.metadata.unwrap_or_default().android {
+            merge_value(&mut metadata, android);
+        }
+        let metadata = select_variant(metadata, variant)?;
+        let mut metadata = select_profile(metadata, profile);
+        let target_sdk = metadata
+            .get("sdk")
+            .and_then(|sdk| sdk.get("target_sdk_version"))
+            .and_then(|version| version.as_integer())
+            .map_or(default_target_sdk, |version| version as u32);
+        let placeholders = Placeholders {
+            pkg_name: &package.name,
+            profile: profile.to_string(),
+            variant,
+            target_sdk,
+        };
+        placeholders.expand(&mut metadata, "package.metadata.android")?;
+        let sources = Sources::new(
+            path,
+            workspace.map(|(path, _)| path),
+            &profile.to_string(),
+            variant,
+        );
+        validate_metadata::<AndroidMetadata>(&metadata, &sources)?;
+        let metadata = AndroidMetadata::from_value(metadata)?;
         Ok(Self {
             version: package.version,
             apk_name: metadata.apk_name,
@@ -50,9 +101,20 @@ impl Manifest {
             assets: metadata.assets,
             resources: metadata.resources,
             runtime_libs: metadata.runtime_libs,
+            java_sources: metadata.java_sources,
+            jars: metadata.jars,
+            aars: metadata.aars,
+            activity_backend: metadata.activity_backend,
+            game_activity_version: metadata.game_activity_version,
+            split_abis: metadata.split_abis,
+            reproducible: metadata.reproducible,
             signing: metadata.signing,
+            signer: metadata.signer,
             reverse_port_forward: metadata.reverse_port_forward,
             strip: metadata.strip,
+            missing_libs: metadata.missing_libs,
+            allowed_missing_libs: metadata.allowed_missing_libs,
+            page_size_16k: metadata.page_size_16k,
         })
     }
 }
@@ -68,10 +130,72 @@ impl Root {
         let contents = std::fs::read_to_string(path)?;
         toml::from_str(&contents).map_err(|e| e.into())
     }
+
+    /// The `[workspace.metadata.android]` table that packages inherit from, with its
+    /// relative paths, also those in its variants and profiles, resolved against
+    /// `workspace_dir`
+    pub(crate) fn workspace_android_metadata(&self, workspace_dir: &Path) -> Option<toml::Value> {
+        let mut metadata = self
+            .workspace
+            .as_ref()?
+            .metadata
+            .as_ref()?
+            .android
+            .clone()?;
+        resolve_paths(&mut metadata, workspace_dir);
+        Some(metadata)
+    }
+}
+
+/// Joins the relative paths in `metadata`, and in its `variants` and `profile` tables,
+/// onto `dir`
+fn resolve_paths(metadata: &mut toml::Value, dir: &Path) {
+    let Some(table) = metadata.as_table_mut() else {
+        return;
+    };
+    let resolve = |value: &mut toml::Value| {
+        if let toml::Value::String(path) = value {
+            *path = dir.join(&*path).to_string_lossy().into_owned();
+        }
+    };
+    for (key, value) in table.iter_mut() {
+        match key.as_str() {
+            "assets" | "resources" | "runtime_libs" | "java_sources" => resolve(value),
+            "jars" | "aars" | "android_manifest_overlays" => {
+                if let toml::Value::Array(paths) = value {
+                    paths.iter_mut().for_each(resolve);
+                }
+            }
+            "signing" => {
+                for signing in value
+                    .as_table_mut()
+                    .into_iter()
+                    .flat_map(|t| t.iter_mut().map(|(_, v)| v))
+                {
+                    for key in ["path", "certificate"] {
+                        if let Some(path) = signing.get_mut(key) {
+                            resolve(path);
+                        }
+                    }
+                }
+            }
+            "variants" | "profile" => {
+                for nested in value
+                    .as_table_mut()
+                    .into_iter()
+                    .flat_map(|t| t.iter_mut().map(|(_, v)| v))
+                {
+                    resolve_paths(nested, dir);
+                }
+            }
+            _ => {}
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub(crate) struct Package {
+    pub(crate) name: String,
     pub(crate) version: Inheritable<String>,
     pub(crate) metadata: Option<PackageMetadata>,
 }
@@ -79,6 +203,7 @@ pub(crate) struct Package {
 #[derive(Clone, Debug, Deserialize)]
 pub(crate) struct Workspace {
     pub(crate) package: Option<WorkspacePackage>,
+    pub(crate) metadata: Option<PackageMetadata>,
 }
 
 /// Almost the same as [`Package`], except that this must provide
@@ -90,31 +215,343 @@ pub(crate) struct WorkspacePackage {
 
 #[derive(Clone, Debug, Default, Deserialize)]
 pub(crate) struct PackageMetadata {
-    android: Option<AndroidMetadata>,
+    /// Kept as a raw value so that variants can be merged over it before it is
+    /// deserialized into [`AndroidMetadata`]
+    android: Option<toml::Value>,
 }
 
-#[derive(Clone, Debug, Default, Deserialize)]
-struct AndroidMetadata {
+/// Removes the `variants` table from `metadata`, and merges the table of `variant` over
+/// what remains
+fn select_variant(mut metadata: toml::Value, variant: Option<&str>) -> Result<toml::Value, Error> {
+    let mut variants = match metadata.as_table_mut().and_then(|t| t.remove("variants")) {
+        Some(toml::Value::Table(variants)) => variants,
+        _ => Default::default(),
+    };
+    if let Some(variant) = variant {
+        let overlay = variants.remove(variant).ok_or_else(|| {
+            Error::UnknownVariant(variant.to_string(), variants.keys().cloned().collect())
+        })?;
+        merge_value(&mut metadata, overlay);
+    }
+    Ok(metadata)
+}
+
+/// Removes the `profile` table from `metadata`, and merges the table of `profile` over
+/// what remains. Profiles without a table, including custom profiles that inherit
+/// from one that has a table, use the base values.
+fn select_profile(mut metadata: toml::Value, profile: &Profile) -> toml::Value {
+    let overlay = match metadata.as_table_mut().and_then(|t| t.remove("profile")) {
+        Some(toml::Value::Table(mut profiles)) => profiles.remove(&profile.to_string()),
+        _ => None,
+    };
+    if let Some(overlay) = overlay {
+        merge_value(&mut metadata, overlay);
+    }
+    metadata
+}
+
+/// Merges `overlay` into `base`: tables are merged key by key, any other value in
+/// `overlay`, including arrays, replaces the one in `base`
+fn merge_value(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base) => merge_value(base, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// The cargo-apk specific options in `[package.metadata.android]`, which shares its
+/// top-level table with the [`AndroidManifest`]. The latter is deserialized separately
+/// in [`AndroidMetadata::from_value()`], rather than flattened into this struct, so that
+/// `serde` exposes the fields of both for [`validate_metadata()`].
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+pub(crate) struct AndroidMetadata {
+    /// Name of the APK file, defaults to the package name
     apk_name: Option<String>,
-    #[serde(flatten)]
+    #[serde(skip)]
     android_manifest: AndroidManifest,
+    /// Targets to build for, defaults to the connected device or all targets
     #[serde(default)]
     build_targets: Vec<Target>,
+    /// Directory with the assets of the application
     assets: Option<PathBuf>,
+    /// Directory with the resources of the application, laid out as
+    /// `<type>[-<qualifiers>]/<file>`
     resources: Option<PathBuf>,
+    /// Directory with extra shared libraries in `<android_abi>/*.so` to add to the APK
     runtime_libs: Option<PathBuf>,
+    /// Directory with Java and Kotlin sources to compile into `classes.dex`
+    java_sources: Option<PathBuf>,
+    /// Prebuilt JARs to compile against and dex into `classes.dex`
+    #[serde(default)]
+    jars: Vec<PathBuf>,
+    /// Android Archive libraries to add the manifest, resources, classes and native
+    /// libraries of to the APK
+    #[serde(default)]
+    aars: Vec<PathBuf>,
+    /// Java activity hosting the native code, matching the `android-activity` backend
+    #[serde(default)]
+    activity_backend: ActivityBackend,
+    /// Version of `androidx.games:games-activity` to bundle for the `game-activity` backend
+    game_activity_version: Option<String>,
+    /// Emit a configuration split APK per ABI instead of a single fat APK
+    #[serde(default)]
+    split_abis: bool,
+    /// Produce byte-identical APKs for identical inputs
+    #[serde(default)]
+    reproducible: bool,
     /// Maps profiles to keystores
     #[serde(default)]
     signing: HashMap<String, Signing>,
+    /// Backend used to sign the APK
+    #[serde(default)]
+    signer: SignerBackend,
     /// Set up reverse port forwarding before launching the application
     #[serde(default)]
     reverse_port_forward: HashMap<String, String>,
+    /// How debug symbols are handled when copying libraries into the APK
     #[serde(default)]
     strip: StripConfig,
+    /// Whether a library dependency that is not found fails the build, prints a
+    /// warning or is ignored
+    #[serde(default)]
+    missing_libs: MissingLibs,
+    /// Libraries that exist on the target devices without being part of the NDK, such
+    /// as vendor libraries, which may contain `*` wildcards
+    #[serde(default)]
+    allowed_missing_libs: Vec<String>,
+    /// Link with 16 KiB aligned segments and fail when a 64-bit library isn't aligned for
+    /// devices with 16 KiB pages
+    #[serde(default)]
+    page_size_16k: bool,
 }
 
-#[derive(Clone, Debug, Default, Deserialize)]
+impl AndroidMetadata {
+    fn from_value(metadata: toml::Value) -> Result<Self, toml::de::Error> {
+        let android_manifest = metadata.clone().try_into()?;
+        Ok(Self {
+            android_manifest,
+            ..metadata.try_into()?
+        })
+    }
+
+    #[cfg(test)]
+    fn from_str(toml: &str) -> Result<Self, toml::de::Error> {
+        Self::from_value(toml::from_str(toml)?)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
 pub(crate) struct Signing {
     pub(crate) path: PathBuf,
+    #[serde(default)]
     pub(crate) keystore_password: String,
+    /// Certificate belonging to a PEM private key at `path`
+    pub(crate) certificate: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variant_merges_over_base() {
+        let metadata: toml::Value = toml::from_str(
+            r#"
+            package = "com.example.app"
+            uses_permission = [{ name = "android.permission.INTERNET" }]
+
+            [application]
+            label = "Example"
+            debuggable = false
+
+            [variants.dev]
+            package = "com.example.app.dev"
+            uses_permission = []
+
+            [variants.dev.application]
+            label = "Example (dev)"
+            "#,
+        )
+        .unwrap();
+
+        let base =
+            AndroidMetadata::from_value(select_variant(metadata.clone(), None).unwrap()).unwrap();
+        assert_eq!(base.android_manifest.package, "com.example.app");
+        assert_eq!(base.android_manifest.uses_permission.len(), 1);
+
+        let dev =
+            AndroidMetadata::from_value(select_variant(metadata.clone(), Some("dev")).unwrap())
+                .unwrap();
+        let manifest = dev.android_manifest;
+        assert_eq!(manifest.package, "com.example.app.dev");
+        assert!(manifest.uses_permission.is_empty());
+        assert_eq!(manifest.application.label, "Example (dev)");
+        assert_eq!(manifest.application.debuggable, Some(false));
+
+        assert!(matches!(
+            select_variant(metadata.clone(), Some("prod")),
+            Err(Error::UnknownVariant(variant, known)) if variant == "prod" && known == ["dev"]
+        ));
+    }
+
+    #[test]
+    fn profile_merges_over_variant() {
+        let metadata: toml::Value = toml::from_str(
+            r#"
+            apk_name = "example"
+            strip = "default"
+
+            [profile.release]
+            strip = "strip"
+
+            [profile.dev.application]
+            uses_cleartext_traffic = true
+
+            [profile.dev.reverse_port_forward]
+            "tcp:1338" = "tcp:1338"
+
+            [variants.staging]
+            apk_name = "example-staging"
+
+            [variants.staging.profile.profiling]
+            uses_permission = [{ name = "android.permission.PACKAGE_USAGE_STATS" }]
+            "#,
+        )
+        .unwrap();
+
+        let select = |profile, variant| -> AndroidMetadata {
+            let metadata = select_variant(metadata.clone(), variant).unwrap();
+            AndroidMetadata::from_value(select_profile(metadata, &profile)).unwrap()
+        };
+
+        let dev = select(Profile::Dev, None);
+        assert_eq!(dev.strip, StripConfig::Default);
+        assert_eq!(
+            dev.android_manifest.application.uses_cleartext_traffic,
+            Some(true)
+        );
+        assert_eq!(dev.reverse_port_forward.len(), 1);
+
+        let release = select(Profile::Release, None);
+        assert_eq!(release.strip, StripConfig::Strip);
+        assert_eq!(
+            release.android_manifest.application.uses_cleartext_traffic,
+            None
+        );
+        assert!(release.reverse_port_forward.is_empty());
+
+        let profiling = select(Profile::Custom("profiling".to_string()), Some("staging"));
+        assert_eq!(profiling.apk_name.as_deref(), Some("example-staging"));
+        assert_eq!(profiling.android_manifest.uses_permission.len(), 1);
+        assert!(select(Profile::Custom("profiling".to_string()), None)
+            .android_manifest
+            .uses_permission
+            .is_empty());
+    }
+
+    #[test]
+    fn inherits_workspace_metadata() {
+        let root: Root = toml::from_str(
+            r#"
+            [workspace.metadata.android]
+            build_targets = ["aarch64-linux-android"]
+            assets = "assets"
+            uses_permission = [{ name = "android.permission.INTERNET" }]
+
+            [workspace.metadata.android.sdk]
+            min_sdk_version = 26
+            target_sdk_version = 34
+
+            [workspace.metadata.android.signing.release]
+            path = "keys/release.keystore"
+            keystore_password = "android"
+
+            [workspace.metadata.android.variants.staging]
+            resources = "res-staging"
+            "#,
+        )
+        .unwrap();
+        let workspace_dir = Path::new("/workspace");
+        let mut metadata = root.workspace_android_metadata(workspace_dir).unwrap();
+        let package: toml::Value = toml::from_str(
+            r#"
+            resources = "res"
+
+            [sdk]
+            target_sdk_version = 35
+            "#,
+        )
+        .unwrap();
+        merge_value(&mut metadata, package);
+
+        let metadata =
+            AndroidMetadata::from_value(select_variant(metadata.clone(), None).unwrap()).unwrap();
+        assert_eq!(metadata.build_targets, [Target::Arm64V8a]);
+        assert_eq!(metadata.assets, Some(workspace_dir.join("assets")));
+        assert_eq!(metadata.resources, Some(PathBuf::from("res")));
+        assert_eq!(metadata.android_manifest.uses_permission.len(), 1);
+        assert_eq!(metadata.android_manifest.sdk.min_sdk_version, Some(26));
+        assert_eq!(metadata.android_manifest.sdk.target_sdk_version, Some(35));
+        assert_eq!(
+            metadata.signing["release"].path,
+            workspace_dir.join("keys/release.keystore")
+        );
+    }
+
+    #[test]
+    fn activity_table_or_array() {
+        let single = AndroidMetadata::from_str(
+            r#"
+            [application.activity]
+            name = "com.example.MainActivity"
+            "#,
+        )
+        .unwrap();
+        let activities = &single.android_manifest.application.activity;
+        assert_eq!(activities.len(), 1);
+        assert_eq!(activities[0].name, "com.example.MainActivity");
+
+        let multiple = AndroidMetadata::from_str(
+            r#"
+            [[application.activity]]
+            name = "com.example.FlatActivity"
+
+            [[application.activity]]
+            name = "com.example.VrActivity"
+            launcher = true
+
+            [[application.activity_alias]]
+            name = ".AlternateIcon"
+            target_activity = "com.example.VrActivity"
+            "#,
+        )
+        .unwrap();
+        let application = &multiple.android_manifest.application;
+        assert_eq!(application.activity.len(), 2);
+        assert_eq!(
+            application.launcher_activity().unwrap().name,
+            "com.example.VrActivity"
+        );
+        assert_eq!(application.activity_alias.len(), 1);
+
+        let default = AndroidMetadata::from_str("").unwrap();
+        assert_eq!(
+            default
+                .android_manifest
+                .application
+                .launcher_activity()
+                .unwrap()
+                .name,
+            "android.app.NativeActivity"
+        );
+    }
 }
@@ -0,0 +1,124 @@
+use crate::error::Error;
+
+/// Values for the `${...}` placeholders in string fields of `[package.metadata.android]`
+pub(crate) struct Placeholders<'a> {
+    pub(crate) pkg_name: &'a str,
+    pub(crate) profile: String,
+    pub(crate) variant: Option<&'a str>,
+    pub(crate) target_sdk: u32,
+}
+
+impl Placeholders<'_> {
+    /// Expands the placeholders in every string in `value`, recursively. `path` is the
+    /// dotted path of `value`, which is reported for unresolved placeholders.
+    ///
+    /// `${applicationId}` is kept as is, it is substituted when the manifest is written.
+    pub(crate) fn expand(&self, value: &mut toml::Value, path: &str) -> Result<(), Error> {
+        match value {
+            toml::Value::String(string) => *string = self.expand_str(string, path)?,
+            toml::Value::Array(array) => {
+                for (i, value) in array.iter_mut().enumerate() {
+                    self.expand(value, &format!("{path}[{i}]"))?;
+                }
+            }
+            toml::Value::Table(table) => {
+                for (key, value) in table.iter_mut() {
+                    self.expand(value, &format!("{path}.{key}"))?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn expand_str(&self, string: &str, path: &str) -> Result<String, Error> {
+        let mut expanded = String::with_capacity(string.len());
+        let mut rest = string;
+        while let Some(start) = rest.find("${") {
+            expanded.push_str(&rest[..start]);
+            let unresolved = |placeholder: &str| Error::UnresolvedPlaceholder {
+                field: path.to_string(),
+                placeholder: placeholder.to_string(),
+            };
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| unresolved(&rest[start..]))?
+                + start;
+            let placeholder = &rest[start..=end];
+            let value = match &rest[start + 2..end] {
+                "applicationId" => placeholder.to_string(),
+                "cargo:pkg_name" => self.pkg_name.to_string(),
+                "profile" => self.profile.clone(),
+                "target_sdk" => self.target_sdk.to_string(),
+                "variant" => self
+                    .variant
+                    .ok_or_else(|| unresolved(placeholder))?
+                    .to_string(),
+                name => match name.strip_prefix("env:") {
+                    Some(var) => std::env::var(var).map_err(|_| unresolved(placeholder))?,
+                    None => return Err(unresolved(placeholder)),
+                },
+            };
+            expanded.push_str(&value);
+            rest = &rest[end + 1..];
+        }
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_placeholders() {
+        let placeholders = Placeholders {
+            pkg_name: "example",
+            profile: "release".to_string(),
+            variant: Some("staging"),
+            target_sdk: 35,
+        };
+        std::env::set_var("CARGO_APK_TEST_BUILD_NUMBER", "42");
+        let mut value: toml::Value = toml::from_str(
+            r#"
+            package = "com.example.${cargo:pkg_name}.${variant}"
+
+            [[application.meta_data]]
+            name = "build"
+            value = "${env:CARGO_APK_TEST_BUILD_NUMBER}-${profile}-sdk${target_sdk}"
+
+            [[application.provider]]
+            authorities = "${applicationId}.files"
+            "#,
+        )
+        .unwrap();
+        placeholders.expand(&mut value, "android").unwrap();
+        assert_eq!(
+            value["package"].as_str(),
+            Some("com.example.example.staging")
+        );
+        assert_eq!(
+            value["application"]["meta_data"][0]["value"].as_str(),
+            Some("42-release-sdk35")
+        );
+        assert_eq!(
+            value["application"]["provider"][0]["authorities"].as_str(),
+            Some("${applicationId}.files")
+        );
+
+        let mut value: toml::Value = toml::from_str(
+            r#"
+            [[application.meta_data]]
+            value = "${env:CARGO_APK_TEST_UNDEFINED}"
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            placeholders.expand(&mut value, "android"),
+            Err(Error::UnresolvedPlaceholder { field, placeholder })
+                if field == "android.application.meta_data[0].value"
+                    && placeholder == "${env:CARGO_APK_TEST_UNDEFINED}"
+        ));
+    }
+}
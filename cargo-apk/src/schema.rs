@@ -0,0 +1,184 @@
+use crate::manifest::AndroidMetadata;
+use ndk_build::manifest::AndroidManifest;
+use schemars::gen::SchemaSettings;
+use schemars::schema::{InstanceType, ObjectValidation, RootSchema, Schema, SchemaObject};
+use schemars::JsonSchema;
+use serde_json::Value;
+
+/// Generates the JSON Schema of `[package.metadata.android]` from the types it is
+/// deserialized into, with their doc comments as descriptions.
+///
+/// The manifest and the `cargo-apk` options share the top-level table, so their
+/// properties are merged into the root schema. `variants` and `profile` tables contain
+/// overrides of the same fields.
+pub fn schema() -> String {
+    let mut generator = SchemaSettings::draft2019_09()
+        .with(|settings| settings.option_add_null_type = false)
+        .into_generator();
+    let mut properties = schemars::Map::new();
+    for schema in [
+        AndroidManifest::json_schema(&mut generator),
+        AndroidMetadata::json_schema(&mut generator),
+    ] {
+        if let Schema::Object(SchemaObject {
+            object: Some(object),
+            ..
+        }) = schema
+        {
+            properties.extend(object.properties);
+        }
+    }
+    let overrides = |description: &str| {
+        let mut schema = SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            object: Some(Box::new(ObjectValidation {
+                additional_properties: Some(Box::new(Schema::new_ref("#".to_string()))),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        schema.metadata().description = Some(description.to_string());
+        Schema::Object(schema)
+    };
+    properties.insert(
+        "variants".to_string(),
+        overrides(
+            "Build variants, selected with `--variant <name>`, that are merged over this table",
+        ),
+    );
+    properties.insert(
+        "profile".to_string(),
+        overrides("Overrides for a cargo profile, merged over this table and the selected variant"),
+    );
+
+    let mut schema = SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        object: Some(Box::new(ObjectValidation {
+            properties,
+            additional_properties: Some(Box::new(Schema::Bool(false))),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    schema.metadata().title = Some("[package.metadata.android]".to_string());
+    let root = RootSchema {
+        meta_schema: generator.settings().meta_schema.clone(),
+        schema,
+        definitions: generator.take_definitions(),
+    };
+
+    let mut root = serde_json::to_value(root).unwrap();
+    clean_up(&mut root);
+    serde_json::to_string_pretty(&root).unwrap()
+}
+
+/// Removes rustdoc links from descriptions, and drops `required` properties as any field
+/// may be set by the workspace, a variant or a profile instead
+fn clean_up(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            object.remove("required");
+            for (key, value) in object.iter_mut() {
+                match value {
+                    Value::String(description) if key == "description" => {
+                        *description = strip_doc_links(description);
+                    }
+                    value => clean_up(value),
+                }
+            }
+        }
+        Value::Array(array) => array.iter_mut().for_each(clean_up),
+        _ => {}
+    }
+}
+
+/// Replaces rustdoc links such as ``[`Type`]``, `[Type]`, ``[`Type`](crate::Type)`` and
+/// `[text](Type::method())` by their text, keeping links to URLs
+fn strip_doc_links(docs: &str) -> String {
+    let mut stripped = String::with_capacity(docs.len());
+    let mut rest = docs;
+    while let Some(start) = rest.find(['[', '`']) {
+        stripped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        // Brackets in code spans aren't links
+        if rest.starts_with('`') {
+            let len = rest[1..].find('`').map_or(rest.len(), |end| end + 2);
+            stripped.push_str(&rest[..len]);
+            rest = &rest[len..];
+            continue;
+        }
+        let Some(end) = rest.find(']') else {
+            break;
+        };
+        let text = &rest[1..end];
+        let after = &rest[end + 1..];
+        let target = match after.chars().next() {
+            Some(open @ ('(' | '[')) => {
+                let close = if open == '(' { ')' } else { ']' };
+                // Targets such as `Type::method()` contain nested parentheses
+                let mut depth = 0;
+                after
+                    .char_indices()
+                    .find(|&(_, c)| {
+                        depth += (c == open) as i32 - (c == close) as i32;
+                        depth == 0
+                    })
+                    .map_or("", |(len, _)| &after[..=len])
+            }
+            _ => "",
+        };
+        if target.contains("://") {
+            stripped.push_str(&rest[..=end]);
+            stripped.push_str(target);
+        } else {
+            stripped.push_str(text);
+        }
+        rest = &after[target.len()..];
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_schema_merges_manifest_and_options() {
+        let schema = schema();
+        for property in [
+            "\"package\": {",
+            "\"uses_permission\": {",
+            "\"android_manifest_overlays\": {",
+            "\"apk_name\": {",
+            "\"activity_backend\": {",
+            "\"variants\": {",
+            "\"Signing\": {",
+        ]
+        .iter()
+        {
+            assert!(schema.contains(property), "missing {}", property);
+        }
+        assert!(schema.contains("\"description\": \"Maps profiles to keystores\""));
+        assert!(!schema.contains("\"AndroidMetadata\""));
+        assert!(!schema.contains("\"overlays\""));
+        assert!(!schema.contains("\"required\""));
+        assert!(!schema.contains("xml formatting"));
+        assert!(!schema.contains("launcher_activity()"));
+    }
+
+    #[test]
+    fn strips_doc_links() {
+        assert_eq!(
+            strip_doc_links(
+                "A [`Type`], [`method`](Type::method()), [Type] or [launcher activity](Application::launcher_activity())"
+            ),
+            "A `Type`, `method`, Type or launcher activity"
+        );
+        assert_eq!(
+            strip_doc_links("See the [docs](https://example.com) and [`Type`][ref]"),
+            "See the [docs](https://example.com) and `Type`"
+        );
+        assert_eq!(strip_doc_links("`[1.2.0]` and [a"), "`[1.2.0]` and [a");
+    }
+}
@@ -0,0 +1,671 @@
+//! Strict validation of `[package.metadata.android]`.
+//!
+//! [`AndroidMetadata`](crate::manifest) and [`AndroidManifest`] ignore unknown keys when
+//! they are deserialized. To report those, and to report type errors with the path of
+//! the offending field, the merged metadata is first deserialized through [`Tracker`],
+//! which records every key that the types skip, together with the fields the enclosing
+//! struct does accept to suggest a correction. Fields are then located in the manifests
+//! they were read from, through the spans that `toml` records for values.
+
+use crate::error::Error;
+use ndk_build::manifest::AndroidManifest;
+use serde::de::{
+    self, value::BorrowedStrDeserializer, DeserializeOwned, DeserializeSeed, Deserializer,
+    IntoDeserializer, MapAccess, SeqAccess, Visitor,
+};
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A key or array index in the path of a field
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct FieldPath(Vec<Segment>);
+
+impl FieldPath {
+    fn join(&self, segment: Segment) -> Self {
+        let mut path = self.clone();
+        path.0.push(segment);
+        path
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("package.metadata.android")?;
+        for segment in &self.0 {
+            match segment {
+                Segment::Key(key) => write!(f, ".{key}")?,
+                Segment::Index(i) => write!(f, "[{i}]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A key that was skipped during deserialization, and the fields of the struct it is in
+struct UnknownKey {
+    path: FieldPath,
+    candidates: &'static [&'static str],
+}
+
+#[derive(Debug)]
+struct TypeError {
+    path: Option<FieldPath>,
+    message: String,
+}
+
+impl TypeError {
+    /// Attributes the error to `path`, unless it was raised by a nested field
+    fn at(mut self, path: &FieldPath) -> Self {
+        self.path.get_or_insert_with(|| path.clone());
+        self
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+impl de::Error for TypeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self {
+            path: None,
+            message: msg.to_string(),
+        }
+    }
+}
+
+/// Deserializes a [`toml::Value`] like `toml` does, while recording the keys that are
+/// skipped with [`de::IgnoredAny`], which `serde` uses for fields a struct doesn't have
+struct Tracker<'de> {
+    value: &'de toml::Value,
+    path: FieldPath,
+    /// Fields of the struct that `value` is a field of
+    candidates: &'static [&'static str],
+    unknown: &'de RefCell<Vec<UnknownKey>>,
+}
+
+impl<'de> Tracker<'de> {
+    fn visit_table<V: Visitor<'de>>(
+        self,
+        table: &'de toml::value::Table,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TypeError> {
+        visitor.visit_map(TableTracker {
+            entries: table.iter(),
+            value: None,
+            path: self.path,
+            fields,
+            unknown: self.unknown,
+        })
+    }
+}
+
+impl<'de> Deserializer<'de> for Tracker<'de> {
+    type Error = TypeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TypeError> {
+        match self.value {
+            toml::Value::String(s) => visitor.visit_borrowed_str(s),
+            toml::Value::Integer(i) => visitor.visit_i64(*i),
+            toml::Value::Float(f) => visitor.visit_f64(*f),
+            toml::Value::Boolean(b) => visitor.visit_bool(*b),
+            toml::Value::Datetime(d) => visitor.visit_string(d.to_string()),
+            toml::Value::Array(array) => visitor.visit_seq(ArrayTracker {
+                elements: array.iter().enumerate(),
+                path: self.path,
+                unknown: self.unknown,
+            }),
+            toml::Value::Table(table) => self.visit_table(table, &[], visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TypeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, TypeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TypeError> {
+        match self.value {
+            toml::Value::Table(table) => self.visit_table(table, fields, visitor),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TypeError> {
+        match self.value {
+            toml::Value::String(s) => visitor.visit_enum(s.as_str().into_deserializer()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TypeError> {
+        self.unknown.borrow_mut().push(UnknownKey {
+            path: self.path,
+            candidates: self.candidates,
+        });
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map identifier
+    }
+}
+
+struct TableTracker<'de> {
+    entries: toml::map::Iter<'de>,
+    value: Option<(&'de String, &'de toml::Value)>,
+    path: FieldPath,
+    fields: &'static [&'static str],
+    unknown: &'de RefCell<Vec<UnknownKey>>,
+}
+
+impl<'de> MapAccess<'de> for TableTracker<'de> {
+    type Error = TypeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, TypeError> {
+        match self.entries.next() {
+            Some(entry) => {
+                self.value = Some(entry);
+                seed.deserialize(BorrowedStrDeserializer::new(entry.0))
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, TypeError> {
+        let (key, value) = self.value.take().expect("value requested before its key");
+        let path = self.path.join(Segment::Key(key.clone()));
+        seed.deserialize(Tracker {
+            value,
+            path: path.clone(),
+            candidates: self.fields,
+            unknown: self.unknown,
+        })
+        .map_err(|e| e.at(&path))
+    }
+}
+
+struct ArrayTracker<'de> {
+    elements: std::iter::Enumerate<std::slice::Iter<'de, toml::Value>>,
+    path: FieldPath,
+    unknown: &'de RefCell<Vec<UnknownKey>>,
+}
+
+impl<'de> SeqAccess<'de> for ArrayTracker<'de> {
+    type Error = TypeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, TypeError> {
+        let Some((i, value)) = self.elements.next() else {
+            return Ok(None);
+        };
+        let path = self.path.join(Segment::Index(i));
+        seed.deserialize(Tracker {
+            value,
+            path: path.clone(),
+            candidates: &[],
+            unknown: self.unknown,
+        })
+        .map(Some)
+        .map_err(|e| e.at(&path))
+    }
+}
+
+/// Deserializes `metadata` into `T` through a [`Tracker`], recording the skipped keys in
+/// `unknown` and returning the type errors. Every top-level entry is deserialized on its
+/// own, as deserialization stops at the first error, and all fields of the top-level
+/// structs are optional.
+fn track<T: DeserializeOwned>(
+    metadata: &toml::Value,
+    unknown: &RefCell<Vec<UnknownKey>>,
+) -> Vec<TypeError> {
+    let deserialize = |value: &toml::Value| {
+        T::deserialize(Tracker {
+            value,
+            path: FieldPath::default(),
+            candidates: &[],
+            unknown,
+        })
+        .err()
+    };
+    match metadata.as_table() {
+        Some(table) => table
+            .iter()
+            .filter_map(|(key, value)| {
+                let mut entry = toml::value::Table::new();
+                entry.insert(key.clone(), value.clone());
+                deserialize(&toml::Value::Table(entry))
+            })
+            .collect(),
+        None => deserialize(metadata).into_iter().collect(),
+    }
+}
+
+/// The TOML structure of a manifest, with the byte range of every value
+enum SpannedValue {
+    Leaf,
+    Array(Vec<toml::Spanned<SpannedValue>>),
+    Table(BTreeMap<String, toml::Spanned<SpannedValue>>),
+}
+
+impl<'de> Deserialize<'de> for SpannedValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SpannedVisitor;
+
+        impl<'de> Visitor<'de> for SpannedVisitor {
+            type Value = SpannedValue;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a TOML value")
+            }
+
+            fn visit_bool<E>(self, _: bool) -> Result<SpannedValue, E> {
+                Ok(SpannedValue::Leaf)
+            }
+
+            fn visit_i64<E>(self, _: i64) -> Result<SpannedValue, E> {
+                Ok(SpannedValue::Leaf)
+            }
+
+            fn visit_u64<E>(self, _: u64) -> Result<SpannedValue, E> {
+                Ok(SpannedValue::Leaf)
+            }
+
+            fn visit_f64<E>(self, _: f64) -> Result<SpannedValue, E> {
+                Ok(SpannedValue::Leaf)
+            }
+
+            fn visit_str<E>(self, _: &str) -> Result<SpannedValue, E> {
+                Ok(SpannedValue::Leaf)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<SpannedValue, A::Error> {
+                let mut elements = Vec::new();
+                while let Some(element) = seq.next_element()? {
+                    elements.push(element);
+                }
+                Ok(SpannedValue::Array(elements))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<SpannedValue, A::Error> {
+                let mut entries = BTreeMap::new();
+                while let Some((key, value)) = map.next_entry()? {
+                    entries.insert(key, value);
+                }
+                Ok(SpannedValue::Table(entries))
+            }
+        }
+
+        deserializer.deserialize_any(SpannedVisitor)
+    }
+}
+
+/// Start of the first value with a span in `value`, as `toml` doesn't record the spans of
+/// tables declared with a `[header]`
+fn span_start(value: &toml::Spanned<SpannedValue>) -> Option<usize> {
+    if value.start() != value.end() {
+        return Some(value.start());
+    }
+    match value.get_ref() {
+        SpannedValue::Leaf => None,
+        SpannedValue::Array(elements) => elements.iter().find_map(span_start),
+        SpannedValue::Table(entries) => entries.values().filter_map(span_start).min(),
+    }
+}
+
+/// A manifest that `[package.metadata.android]` was merged from
+struct Source {
+    path: PathBuf,
+    text: String,
+    root: toml::Spanned<SpannedValue>,
+}
+
+/// Locates fields of the merged `[package.metadata.android]` in the manifests and tables
+/// they were merged from
+pub(crate) struct Sources {
+    sources: Vec<Source>,
+    /// Indices into `sources` and the table paths to look fields up in, from the highest
+    /// priority to the lowest
+    tables: Vec<(usize, Vec<String>)>,
+}
+
+impl Sources {
+    /// Describes the merge order of [`Manifest::parse_from_toml()`](crate::manifest::Manifest::parse_from_toml)
+    pub(crate) fn new(
+        manifest: &Path,
+        workspace_manifest: Option<&Path>,
+        profile: &str,
+        variant: Option<&str>,
+    ) -> Self {
+        let mut sources = Vec::new();
+        let mut roots = Vec::new();
+        for (path, root) in [
+            (Some(manifest), "package"),
+            (workspace_manifest, "workspace"),
+        ] {
+            let Some(path) = path else { continue };
+            let Ok(text) = std::fs::read_to_string(path) else {
+                continue;
+            };
+            let Ok(spanned) = toml::from_str(&text) else {
+                continue;
+            };
+            roots.push((sources.len(), root));
+            sources.push(Source {
+                path: path.to_owned(),
+                text,
+                root: spanned,
+            });
+        }
+
+        let mut overlays = Vec::new();
+        if let Some(variant) = variant {
+            overlays.push(vec!["variants", variant, "profile", profile]);
+        }
+        overlays.push(vec!["profile", profile]);
+        if let Some(variant) = variant {
+            overlays.push(vec!["variants", variant]);
+        }
+        overlays.push(vec![]);
+
+        let mut tables = Vec::new();
+        for overlay in overlays {
+            for (source, root) in &roots {
+                let table = [*root, "metadata", "android"]
+                    .iter()
+                    .chain(&overlay)
+                    .map(|key| key.to_string())
+                    .collect();
+                tables.push((*source, table));
+            }
+        }
+        Self { sources, tables }
+    }
+
+    /// `file:line:column` of the value of `path`
+    fn locate(&self, path: &FieldPath) -> Option<String> {
+        self.tables.iter().find_map(|(source, table)| {
+            let source = &self.sources[*source];
+            let mut value = &source.root;
+            let keys = table.iter().cloned().map(Segment::Key);
+            for segment in keys.chain(path.0.iter().cloned()) {
+                value = match (value.get_ref(), segment) {
+                    (SpannedValue::Table(entries), Segment::Key(key)) => entries.get(&key)?,
+                    (SpannedValue::Array(elements), Segment::Index(i)) => elements.get(i)?,
+                    _ => return None,
+                };
+            }
+            let offset = span_start(value)?;
+            let before = &source.text[..offset];
+            let line = before.matches('\n').count() + 1;
+            let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+            Some(format!("{}:{}:{}", source.path.display(), line, column))
+        })
+    }
+
+    fn diagnostic(&self, path: &FieldPath, message: String) -> String {
+        match self.locate(path) {
+            Some(location) => format!("{location}: {message}"),
+            None => message,
+        }
+    }
+}
+
+/// The candidate closest to `key`, if it is close enough to be a typo
+fn suggest<'a>(key: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .map(|candidate| (strsim::levenshtein(key, candidate), candidate))
+        .filter(|(distance, _)| *distance <= (key.len() / 3).max(1))
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Checks the merged `metadata` for unknown keys and values that don't deserialize into
+/// `Metadata`, the cargo-apk specific options, or into [`AndroidManifest`]. Both share
+/// the top-level table, so only keys that neither knows about are reported there.
+pub(crate) fn validate_metadata<Metadata: DeserializeOwned>(
+    metadata: &toml::Value,
+    sources: &Sources,
+) -> Result<(), Error> {
+    let unknown_in_manifest = RefCell::default();
+    let unknown_in_metadata = RefCell::default();
+    let mut errors = track::<AndroidManifest>(metadata, &unknown_in_manifest);
+    errors.extend(track::<Metadata>(metadata, &unknown_in_metadata));
+    let unknown_in_manifest = unknown_in_manifest.into_inner();
+    let unknown_in_metadata = unknown_in_metadata.into_inner();
+
+    let top_level = |unknown: &[UnknownKey]| {
+        unknown
+            .iter()
+            .filter(|key| key.path.0.len() == 1)
+            .map(|key| key.path.0[0].clone())
+            .collect::<Vec<_>>()
+    };
+    let top_level_in_manifest = top_level(&unknown_in_manifest);
+    let top_level_in_metadata = top_level(&unknown_in_metadata);
+
+    let mut diagnostics = Vec::new();
+    let mut reported = HashSet::new();
+    for key in unknown_in_manifest.iter().chain(&unknown_in_metadata) {
+        let nested = key.path.0.len() > 1;
+        // Top-level keys are only unknown when neither struct accepts them
+        let known_at_top_level = !top_level_in_manifest.contains(&key.path.0[0])
+            || !top_level_in_metadata.contains(&key.path.0[0]);
+        if !nested && known_at_top_level {
+            continue;
+        }
+        if !reported.insert(key.path.to_string()) {
+            continue;
+        }
+        let candidates: Vec<&str> = if nested {
+            key.candidates.to_vec()
+        } else {
+            unknown_in_manifest
+                .iter()
+                .chain(&unknown_in_metadata)
+                .filter(|key| key.path.0.len() == 1)
+                .flat_map(|key| key.candidates.iter().copied())
+                .collect()
+        };
+        let name = match key.path.0.last() {
+            Some(Segment::Key(name)) => name.as_str(),
+            _ => continue,
+        };
+        let mut message = format!("unknown key `{}`", key.path);
+        if let Some(suggestion) = suggest(name, candidates.into_iter()) {
+            message += &format!(", did you mean `{suggestion}`?");
+        }
+        diagnostics.push(sources.diagnostic(&key.path, message));
+    }
+
+    for error in errors {
+        let path = error.path.unwrap_or_default();
+        let message = format!("invalid value for `{}`: {}", path, error.message);
+        diagnostics.push(sources.diagnostic(&path, message));
+    }
+
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::InvalidMetadata(diagnostics))
+    }
+}
+
+/// Checks that `package` is a valid application ID: at least two segments separated by
+/// dots, each starting with a letter and consisting of letters, digits and underscores
+pub(crate) fn validate_package_name(package: &str) -> Result<(), Error> {
+    let valid_segment = |segment: &str| {
+        segment
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic())
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    if package.split('.').count() >= 2 && package.split('.').all(valid_segment) {
+        Ok(())
+    } else {
+        Err(Error::InvalidPackageName(package.to_string()))
+    }
+}
+
+/// Checks the SDK versions and permissions of `manifest`, after cargo-apk filled in its
+/// default `target_sdk_version`
+pub(crate) fn validate_manifest(manifest: &AndroidManifest) -> Result<(), Error> {
+    let sdk = &manifest.sdk;
+    let min = sdk.min_sdk_version.unwrap_or(1);
+    let target = sdk.target_sdk_version.unwrap_or(min);
+    if min > target || sdk.max_sdk_version.is_some_and(|max| max < min.max(target)) {
+        return Err(Error::InconsistentSdkVersions {
+            min: sdk.min_sdk_version,
+            target: sdk.target_sdk_version,
+            max: sdk.max_sdk_version,
+        });
+    }
+
+    let mut permissions = HashSet::new();
+    for permission in &manifest.uses_permission {
+        if !permissions.insert(&permission.name) {
+            return Err(Error::DuplicatePermission(permission.name.clone()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Metadata {
+        apk_name: Option<String>,
+        #[serde(default)]
+        split_abis: bool,
+    }
+
+    fn validate(toml: &str) -> Vec<String> {
+        let dir = std::env::temp_dir().join(format!("cargo-apk-validate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let manifest = dir.join("Cargo.toml");
+        std::fs::write(&manifest, toml).unwrap();
+        let root: toml::Value = toml::from_str(toml).unwrap();
+        let metadata = &root["package"]["metadata"]["android"];
+        let sources = Sources::new(&manifest, None, "dev", None);
+        match validate_metadata::<Metadata>(metadata, &sources) {
+            Ok(()) => vec![],
+            Err(Error::InvalidMetadata(diagnostics)) => diagnostics
+                .into_iter()
+                .map(|d| d.replace(&manifest.display().to_string(), "Cargo.toml"))
+                .collect(),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn reports_unknown_keys_and_type_errors() {
+        let diagnostics = validate(
+            r#"[package]
+name = "example"
+
+[package.metadata.android]
+apk_name = "example"
+split_abi = true
+
+[package.metadata.android.sdk]
+min_sdk_version = "23"
+
+[package.metadata.android.aplication]
+label = "Example"
+
+[[package.metadata.android.application.activity]]
+orientaton = "landscape"
+"#,
+        );
+        assert_eq!(
+            diagnostics,
+            [
+                "Cargo.toml:12:9: unknown key `package.metadata.android.aplication`, did you mean `application`?",
+                "Cargo.toml:15:14: unknown key `package.metadata.android.application.activity[0].orientaton`, did you mean `orientation`?",
+                "Cargo.toml:6:13: unknown key `package.metadata.android.split_abi`, did you mean `split_abis`?",
+                "Cargo.toml:9:19: invalid value for `package.metadata.android.sdk.min_sdk_version`: invalid type: string \"23\", expected u32",
+            ]
+        );
+
+        // A single activity table is checked against the fields of `Activity` as well
+        assert_eq!(
+            validate(
+                r#"[package]
+name = "example"
+
+[package.metadata.android.application.activity]
+orientaton = "landscape"
+"#,
+            ),
+            ["Cargo.toml:5:14: unknown key `package.metadata.android.application.activity.orientaton`, did you mean `orientation`?"]
+        );
+
+        assert!(validate(
+            r#"[package]
+name = "example"
+
+[package.metadata.android]
+apk_name = "example"
+split_abis = true
+
+[package.metadata.android.application]
+label = "Example"
+"#
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn package_names() {
+        assert!(validate_package_name("com.example.app").is_ok());
+        assert!(validate_package_name("rust.my_game2").is_ok());
+        assert!(validate_package_name("example").is_err());
+        assert!(validate_package_name("rust.2d_game").is_err());
+        assert!(validate_package_name("com.example-app").is_err());
+        assert!(validate_package_name("com..example").is_err());
+    }
+}
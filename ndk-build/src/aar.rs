@@ -0,0 +1,211 @@
+//! Consumption of Android Archive (`.aar`) libraries.
+//!
+//! Every AAR listed in [`ApkConfig::aars`] is extracted to the build directory and
+//! contributes:
+//!
+//! - its `AndroidManifest.xml`, merged into the generated manifest with the lowest
+//!   priority, see [`manifest_merger`](crate::manifest_merger);
+//! - its `res/` and `assets/`, linked with the resources and assets of the app;
+//! - its `classes.jar` and `libs/*.jar`, dexed into `classes.dex`;
+//! - its `jni/<abi>/*.so` native libraries.
+//!
+//! See <https://developer.android.com/studio/projects/android-library#aar-contents>.
+
+use crate::apk::{ApkConfig, UnalignedApk};
+use crate::error::NdkError;
+use crate::fingerprint::InputHasher;
+use crate::manifest_merger::Element;
+use crate::target::Target;
+use crate::zip::ZipArchive;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// An Android Archive library, extracted to the build directory
+#[derive(Clone, Debug)]
+pub struct Aar {
+    dir: PathBuf,
+    package: Option<String>,
+}
+
+impl Aar {
+    /// Extracts `aar` into `dir`, unless it was already extracted there and did not
+    /// change since.
+    ///
+    /// The hash of the extracted AAR is stored in `dir`, as modification times don't tell
+    /// when an AAR is replaced by an older copy, e.g. by a checkout or from a Maven cache.
+    pub fn extract(aar: &Path, dir: &Path) -> Result<Self, NdkError> {
+        let stamp = dir.join(".extracted");
+        let data = fs::read(aar).map_err(|e| NdkError::IoPathError(aar.to_owned(), e))?;
+        let mut hasher = InputHasher::default();
+        hasher.bytes(&data);
+        let hash = hasher.finish();
+        let up_to_date = fs::read_to_string(&stamp).is_ok_and(|stamp| stamp == hash);
+
+        if !up_to_date {
+            if dir.exists() {
+                fs::remove_dir_all(dir).map_err(|e| NdkError::IoPathError(dir.to_owned(), e))?;
+            }
+            let archive = ZipArchive::parse(&data)?;
+            for entry in archive.entries() {
+                if entry.name.ends_with('/') {
+                    continue;
+                }
+                let name = Path::new(&entry.name);
+                if !name.components().all(|c| matches!(c, Component::Normal(_))) {
+                    return Err(NdkError::InvalidZip(format!(
+                        "entry `{}` of `{}` escapes the archive",
+                        entry.name,
+                        aar.display()
+                    )));
+                }
+                let path = dir.join(name);
+                fs::create_dir_all(path.parent().unwrap())?;
+                fs::write(&path, archive.read(entry)?)
+                    .map_err(|e| NdkError::IoPathError(path, e))?;
+            }
+            fs::write(&stamp, hash).map_err(|e| NdkError::IoPathError(stamp, e))?;
+        }
+
+        let manifest = dir.join("AndroidManifest.xml");
+        let xml = fs::read_to_string(&manifest)
+            .map_err(|e| NdkError::IoPathError(manifest.clone(), e))?;
+        let package = Element::parse(&xml, &manifest.display().to_string(), true)
+            .map_err(NdkError::ManifestMerge)?
+            .attribute("package")
+            .map(str::to_string);
+
+        Ok(Self {
+            dir: dir.to_owned(),
+            package,
+        })
+    }
+
+    /// Package of the library, under which `aapt2` generates its `R` class
+    pub fn package(&self) -> Option<&str> {
+        self.package.as_deref()
+    }
+
+    pub fn manifest(&self) -> PathBuf {
+        self.dir.join("AndroidManifest.xml")
+    }
+
+    pub fn res(&self) -> Option<PathBuf> {
+        Some(self.dir.join("res")).filter(|res| res.is_dir())
+    }
+
+    pub fn assets(&self) -> Option<PathBuf> {
+        Some(self.dir.join("assets")).filter(|assets| assets.is_dir())
+    }
+
+    /// Directory holding the `.flat` files of the compiled [`Aar::res`]
+    pub(crate) fn compiled_resources_dir(&self) -> PathBuf {
+        self.dir.join("compiled_res")
+    }
+
+    /// The `classes.jar` of the library followed by the JARs it bundles in `libs/`
+    pub fn jars(&self) -> Result<Vec<PathBuf>, NdkError> {
+        let mut jars = Vec::new();
+        let classes = self.dir.join("classes.jar");
+        if classes.exists() {
+            jars.push(classes);
+        }
+        let libs = self.dir.join("libs");
+        if libs.is_dir() {
+            let mut bundled = Vec::new();
+            for entry in fs::read_dir(&libs).map_err(|e| NdkError::IoPathError(libs, e))? {
+                let path = entry?.path();
+                if path.extension() == Some(OsStr::new("jar")) {
+                    bundled.push(path);
+                }
+            }
+            bundled.sort();
+            jars.extend(bundled);
+        }
+        Ok(jars)
+    }
+
+    /// Directory holding the native libraries of every ABI, in `jni/<abi>/`
+    pub fn jni_dir(&self) -> PathBuf {
+        self.dir.join("jni")
+    }
+}
+
+impl ApkConfig {
+    /// Extracts every AAR in [`ApkConfig::aars`] to the `aars` directory in the
+    /// build directory
+    pub fn extract_aars(&self) -> Result<Vec<Aar>, NdkError> {
+        self.aars
+            .iter()
+            .enumerate()
+            .map(|(i, aar)| {
+                let stem = aar.file_stem().unwrap_or_default().to_string_lossy();
+                let dir = self.build_dir.join("aars").join(format!("{i}-{stem}"));
+                Aar::extract(aar, &dir)
+            })
+            .collect()
+    }
+
+    /// Writes the manifest to the build directory, with the manifests of `aars`
+    /// merged into it
+    pub(crate) fn write_manifest(&self, aars: &[Aar]) -> Result<(), NdkError> {
+        let libraries = aars.iter().map(Aar::manifest).collect::<Vec<_>>();
+        self.manifest.write_merged_to(&self.build_dir, &libraries)
+    }
+}
+
+impl<'a> UnalignedApk<'a> {
+    /// Adds the native libraries for `target` of every AAR in [`ApkConfig::aars`],
+    /// along with their dependencies found in `search_paths`
+    pub fn add_aar_libs(&mut self, target: Target, search_paths: &[&Path]) -> Result<(), NdkError> {
+        for aar in self.config.extract_aars()? {
+            let jni_dir = aar.jni_dir();
+            if jni_dir.join(target.android_abi()).is_dir() {
+                self.add_runtime_libs(&jni_dir, target, search_paths)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zip::{Compression, DosDateTime, ZipWriter, DEFAULT_ALIGNMENT};
+
+    fn aar(package: &str) -> Vec<u8> {
+        let manifest = format!(r#"<manifest package="{package}"/>"#);
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add_entry(
+            "AndroidManifest.xml",
+            manifest.as_bytes(),
+            Compression::Deflated,
+            DosDateTime::EPOCH,
+            DEFAULT_ALIGNMENT,
+        )
+        .unwrap();
+        zip.finish().unwrap()
+    }
+
+    #[test]
+    fn extracts_replaced_aar() {
+        let dir = std::env::temp_dir().join(format!("ndk-build-aar-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("library.aar");
+        let older = dir.join("older.aar");
+        let extracted = dir.join("library");
+        // Written before the extraction, so that it is older than the stamp
+        fs::write(&older, aar("com.example.older")).unwrap();
+        fs::write(&path, aar("com.example.library")).unwrap();
+
+        let library = Aar::extract(&path, &extracted).unwrap();
+        assert_eq!(library.package(), Some("com.example.library"));
+        let library = Aar::extract(&path, &extracted).unwrap();
+        assert_eq!(library.package(), Some("com.example.library"));
+
+        fs::rename(&older, &path).unwrap();
+        let library = Aar::extract(&path, &extracted).unwrap();
+        assert_eq!(library.package(), Some("com.example.older"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,11 +1,20 @@
+use crate::elf::Elf;
 use crate::error::NdkError;
+use crate::fingerprint::{Fingerprint, InputHasher};
+use crate::keystore::SigningKey;
 use crate::manifest::AndroidManifest;
+use crate::manifest_merger::Element;
+use crate::maven::{Coordinate, Repository};
 use crate::ndk::{Key, Ndk};
+use crate::sign::sign_apk;
 use crate::target::Target;
-use std::collections::HashMap;
-use std::collections::HashSet;
+use crate::zip::{
+    alignment_for, Compression, DosDateTime, ZipArchive, ZipEntry, ZipWriter, PAGE_ALIGNMENT,
+};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::ffi::OsStr;
-use std::fs;
+use std::fs::{self, File};
+use std::io::BufWriter;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
@@ -16,21 +25,103 @@ use std::process::Command;
 /// or [`split-debuginfo`](https://doc.rust-lang.org/cargo/reference/profiles.html#split-debuginfo)
 /// in your cargo manifest(s) may cause debug symbols to not be present in a
 /// `.so`, which would cause these options to do nothing.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Deserialize)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, serde::Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum StripConfig {
     /// Does not treat debug symbols specially
+    #[default]
     Default,
-    /// Removes debug symbols from the library before copying it into the APK
+    /// Removes debug symbols from the library before copying it into the APK. They are
+    /// kept in a DWARF (`.dwarf`) file for [`ApkConfig::native_debug_symbols`]
     Strip,
     /// Splits the library into into an ELF (`.so`) and DWARF (`.dwarf`). Only the
-    /// `.so` is copied into the APK
+    /// `.so` is copied into the APK, with a `.gnu_debuglink` to the `.dwarf`
     Split,
 }
 
-impl Default for StripConfig {
-    fn default() -> Self {
-        Self::Default
+/// The tool used to sign APKs in [`UnsignedApk::sign`]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, serde::Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SignerBackend {
+    /// Signs with the v1, v2 and v3 schemes in-process, see [`crate::sign`]
+    #[default]
+    Native,
+    /// Signs with the `apksigner` script from the Android SDK build tools, which
+    /// requires a Java runtime
+    Apksigner,
+}
+
+/// How [`UnalignedApk::add_lib_recursively()`] handles a library dependency that is
+/// neither found in the search paths nor provided by the platform, and symbols that are
+/// not available on `min_sdk_version`
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, serde::Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum MissingLibs {
+    /// Fails with [`NdkError::MissingSharedLibrary`] or [`NdkError::UnavailableSymbols`]
+    #[default]
+    Error,
+    /// Prints a warning, the APK fails to load the library that depends on it or uses
+    /// the symbols
+    Warn,
+    /// Leaves the dependency out without a warning
+    Ignore,
+}
+
+/// The Java activity that hosts the native code, matching the backend enabled in
+/// the [`android-activity`](https://docs.rs/android-activity) crate
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, serde::Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum ActivityBackend {
+    /// `android.app.NativeActivity`, which is part of the platform
+    #[default]
+    NativeActivity,
+    /// `GameActivity` from the Android Game Development Kit, which is bundled into
+    /// the APK together with its AndroidX dependencies
+    GameActivity,
+}
+
+impl ActivityBackend {
+    /// Version of `androidx.games:games-activity` that is bundled by default, matching
+    /// the C glue code of `android-activity`
+    pub const DEFAULT_GAME_ACTIVITY_VERSION: &'static str = "2.0.2";
+
+    /// Class name of the activity
+    pub fn activity_name(self) -> &'static str {
+        match self {
+            Self::NativeActivity => "android.app.NativeActivity",
+            Self::GameActivity => "com.google.androidgamesdk.GameActivity",
+        }
+    }
+
+    /// Theme that the activity requires, `GameActivity` derives from `AppCompatActivity`
+    pub fn theme(self) -> Option<&'static str> {
+        match self {
+            Self::NativeActivity => None,
+            Self::GameActivity => Some("@style/Theme.AppCompat.NoActionBar"),
+        }
+    }
+
+    /// Downloads the AARs and JARs that implement the activity, if it is not part of
+    /// the platform. `game_activity_version` overrides
+    /// [`Self::DEFAULT_GAME_ACTIVITY_VERSION`].
+    pub fn libraries(
+        self,
+        ndk: &Ndk,
+        game_activity_version: Option<&str>,
+    ) -> Result<Vec<PathBuf>, NdkError> {
+        match self {
+            Self::NativeActivity => Ok(vec![]),
+            Self::GameActivity => {
+                let version = game_activity_version.unwrap_or(Self::DEFAULT_GAME_ACTIVITY_VERSION);
+                let repository =
+                    Repository::new(ndk.android_user_home()?.join("cargo-apk").join("maven"));
+                repository.resolve(&[Coordinate::new("androidx.games", "games-activity", version)])
+            }
+        }
     }
 }
 
@@ -41,21 +132,67 @@ pub struct ApkConfig {
     pub assets: Option<PathBuf>,
     pub resources: Option<PathBuf>,
     pub manifest: AndroidManifest,
+    /// Directory with Java and Kotlin sources, compiled into `classes.dex`
+    pub java_sources: Option<PathBuf>,
+    /// Prebuilt JARs that the sources are compiled against and that are dexed into
+    /// `classes.dex` along with them
+    pub jars: Vec<PathBuf>,
+    /// Android Archive libraries whose manifest, resources, classes and native
+    /// libraries are added to the APK, see the [`aar`](crate::aar) module
+    pub aars: Vec<PathBuf>,
     pub disable_aapt_compression: bool,
     pub strip: StripConfig,
     pub reverse_port_forward: HashMap<String, String>,
+    pub signer: SignerBackend,
+    /// Moves the native libraries of every ABI out of the base APK into a
+    /// configuration split APK, see [`ApkConfig::split_apk`]
+    pub split_abis: bool,
+    /// Produces byte-identical output for identical inputs, by sorting zip entries,
+    /// giving them all the timestamp from [`DosDateTime::from_source_date_epoch`] and
+    /// dropping their file permissions
+    pub reproducible: bool,
+    /// How library dependencies that are not found, and symbols that are not available on
+    /// `min_sdk_version`, are handled
+    pub missing_libs: MissingLibs,
+    /// Names of libraries that exist on the target devices without being part of the
+    /// platform stubs in the NDK, such as vendor libraries. `*` matches any sequence of
+    /// characters.
+    pub allowed_missing_libs: Vec<String>,
+    /// Fails when a 64-bit library is not aligned for devices with 16 KiB pages, instead
+    /// of printing a warning. [`cargo_ndk()`](crate::cargo::cargo_ndk()) links with
+    /// 16 KiB alignment when this is enabled.
+    pub page_size_16k: bool,
 }
 
 impl ApkConfig {
-    fn build_tool(&self, tool: &'static str) -> Result<Command, NdkError> {
+    pub(crate) fn build_tool(&self, tool: &'static str) -> Result<Command, NdkError> {
         let mut cmd = self.ndk.build_tool(tool)?;
         cmd.current_dir(&self.build_dir);
         Ok(cmd)
     }
 
-    fn unaligned_apk(&self) -> PathBuf {
-        self.build_dir
-            .join(format!("{}-unaligned.apk", self.apk_name))
+    /// Name of the activity that is started from the launcher: the enabled activity or
+    /// activity alias with a `MAIN`/`LAUNCHER` intent filter in the final manifest in the
+    /// build directory, which includes the activities of [`AndroidManifest::overlays`] and
+    /// [`ApkConfig::aars`], preferring the [`Application::launcher_activity()`]. Relative
+    /// class names are returned with a leading `.`.
+    ///
+    /// [`AndroidManifest::overlays`]: crate::manifest::AndroidManifest::overlays
+    /// [`Application::launcher_activity()`]: crate::manifest::Application::launcher_activity()
+    pub fn launcher_activity(&self) -> Option<String> {
+        let configured = self
+            .manifest
+            .application
+            .launcher_activity()
+            .map(|activity| activity.name.as_str());
+        let manifest = fs::read_to_string(self.build_dir.join("AndroidManifest.xml"))
+            .ok()
+            .and_then(|xml| Element::parse(&xml, "AndroidManifest.xml", false).ok());
+        let name = match &manifest {
+            Some(manifest) => manifest.launcher_activity(configured).or(configured),
+            None => configured,
+        }?;
+        Some(component_class_name(name))
     }
 
     /// Retrieves the path of the APK that will be written when [`UnsignedApk::sign`]
@@ -65,51 +202,373 @@ impl ApkConfig {
         self.build_dir.join(format!("{}.apk", self.apk_name))
     }
 
-    pub fn create_apk(&self) -> Result<UnalignedApk, NdkError> {
-        std::fs::create_dir_all(&self.build_dir)?;
-        self.manifest.write_to(&self.build_dir)?;
+    /// Retrieves the path of the configuration split APK holding the native libraries
+    /// of `target`, which is only written when [`ApkConfig::split_abis`] is set
+    #[inline]
+    pub fn split_apk(&self, target: Target) -> PathBuf {
+        self.build_dir
+            .join(format!("split_{}.apk", split_name(target)))
+    }
+
+    pub(crate) fn min_sdk_version(&self) -> u32 {
+        let default_min_sdk = crate::manifest::Sdk::default().min_sdk_version.unwrap();
+        self.manifest.sdk.min_sdk_version.unwrap_or(default_min_sdk)
+    }
 
+    pub(crate) fn android_jar(&self) -> Result<PathBuf, NdkError> {
         let target_sdk_version = self
             .manifest
             .sdk
             .target_sdk_version
             .unwrap_or_else(|| self.ndk.default_target_platform());
-        let mut aapt = self.build_tool(bin!("aapt"))?;
-        aapt.arg("package")
-            .arg("-f")
-            .arg("-F")
-            .arg(self.unaligned_apk())
-            .arg("-M")
+        self.ndk.android_jar(target_sdk_version)
+    }
+
+    /// Directory holding the `.flat` files produced by `aapt2 compile`, kept
+    /// around between builds so that only modified resources are recompiled
+    fn compiled_resources_dir(&self) -> PathBuf {
+        self.build_dir.join("compiled_res")
+    }
+
+    /// Compiles every file in the `resources` directory to `aapt2`'s intermediate
+    /// `.flat` format, skipping files whose compiled output is newer than the source.
+    ///
+    /// Every source file gets its own output directory to map it back to the
+    /// (possibly multiple) `.flat` files that `aapt2` generates for it.
+    fn compile_resources(&self, res: &Path, compiled_dir: &Path) -> Result<Vec<PathBuf>, NdkError> {
+        std::fs::create_dir_all(compiled_dir)?;
+
+        let mut flat_files = Vec::new();
+        let mut outputs = HashSet::new();
+        for source in list_resource_files(res)? {
+            let rel_path = source.strip_prefix(res).unwrap();
+            let out_dir = compiled_dir.join(compiled_resource_dir_name(rel_path));
+
+            if needs_recompile(&source, &out_dir)? {
+                if out_dir.exists() {
+                    fs::remove_dir_all(&out_dir)
+                        .map_err(|e| NdkError::IoPathError(out_dir.clone(), e))?;
+                }
+                fs::create_dir_all(&out_dir)?;
+
+                let mut aapt2 = self.build_tool(bin!("aapt2"))?;
+                aapt2.arg("compile").arg("-o").arg(&out_dir).arg(&source);
+                if !aapt2.status()?.success() {
+                    return Err(NdkError::CmdFailed(Box::new(aapt2)));
+                }
+            }
+
+            flat_files.extend(list_flat_files(&out_dir)?);
+            outputs.insert(out_dir);
+        }
+
+        // Drop compiled output of resources that were removed since the previous build
+        for entry in fs::read_dir(compiled_dir)? {
+            let path = entry?.path();
+            if !outputs.contains(&path) {
+                fs::remove_dir_all(&path).map_err(|e| NdkError::IoPathError(path, e))?;
+            }
+        }
+
+        flat_files.sort();
+        Ok(flat_files)
+    }
+
+    /// Links the manifest, compiled resources and assets into `output` with `aapt2`.
+    ///
+    /// With `proto_format`, the manifest and resource table are written in the
+    /// protobuf format expected inside Android App Bundles instead of binary XML.
+    pub(crate) fn link(&self, output: &Path, proto_format: bool) -> Result<(), NdkError> {
+        std::fs::create_dir_all(&self.build_dir)?;
+        let aars = self.extract_aars()?;
+        self.write_manifest(&aars)?;
+
+        let flat_files = match &self.resources {
+            Some(res) => self.compile_resources(res, &self.compiled_resources_dir())?,
+            None => Vec::new(),
+        };
+        let mut aar_flat_files = Vec::new();
+        for aar in &aars {
+            if let Some(res) = aar.res() {
+                aar_flat_files.extend(self.compile_resources(&res, &aar.compiled_resources_dir())?);
+            }
+        }
+
+        let mut aapt2 = self.build_tool(bin!("aapt2"))?;
+        aapt2
+            .arg("link")
+            .arg("-o")
+            .arg(output)
+            .arg("--manifest")
             .arg("AndroidManifest.xml")
             .arg("-I")
-            .arg(self.ndk.android_jar(target_sdk_version)?);
+            .arg(self.android_jar()?);
 
-        if self.disable_aapt_compression {
-            aapt.arg("-0").arg("");
+        if proto_format {
+            aapt2.arg("--proto-format");
         }
 
-        if let Some(res) = &self.resources {
-            aapt.arg("-S").arg(res);
+        if self.disable_aapt_compression {
+            aapt2.arg("--no-compress");
         }
 
         if let Some(assets) = &self.assets {
-            aapt.arg("-A").arg(assets);
+            aapt2.arg("-A").arg(assets);
+        }
+        for assets in aars.iter().filter_map(|aar| aar.assets()) {
+            aapt2.arg("-A").arg(assets);
+        }
+
+        if aar_flat_files.is_empty() {
+            aapt2.args(flat_files);
+        } else {
+            // Link the resources of the app as overlays of those of the AARs, so
+            // that the app can override library resources
+            aapt2.args(aar_flat_files).arg("--auto-add-overlay");
+            for flat_file in flat_files {
+                aapt2.arg("-R").arg(flat_file);
+            }
+
+            // Generate the `R` classes that the code of the AARs refers to
+            let packages = aars
+                .iter()
+                .filter(|aar| aar.res().is_some())
+                .filter_map(|aar| aar.package())
+                .collect::<Vec<_>>();
+            let generated = self.generated_sources_dir();
+            if generated.exists() {
+                fs::remove_dir_all(&generated)
+                    .map_err(|e| NdkError::IoPathError(generated.clone(), e))?;
+            }
+            aapt2
+                .arg("--java")
+                .arg(generated)
+                .arg("--extra-packages")
+                .arg(packages.join(":"));
         }
 
-        if !aapt.status()?.success() {
-            return Err(NdkError::CmdFailed(Box::new(aapt)));
+        if !aapt2.status()?.success() {
+            return Err(NdkError::CmdFailed(Box::new(aapt2)));
         }
+        Ok(())
+    }
+
+    /// Links an empty configuration split APK for the native libraries of `target`,
+    /// returning its path. An existing split is reused unless `relink` is set.
+    fn link_split(&self, target: Target, relink: bool) -> Result<PathBuf, NdkError> {
+        let split = split_name(target);
+        let manifest_dir = self.build_dir.join(format!("split_{split}"));
+        let linked_apk = manifest_dir.join("linked.apk");
+        if !relink && linked_apk.exists() {
+            return Ok(linked_apk);
+        }
+        fs::create_dir_all(&manifest_dir)?;
+        let version_code = self
+            .manifest
+            .version_code
+            .map(|code| format!(r#" android:versionCode="{code}""#))
+            .unwrap_or_default();
+        let manifest = format!(
+            r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android" package="{}"{} split="{}" android:isFeatureSplit="false"><application android:hasCode="false"/></manifest>"#,
+            self.manifest.package, version_code, split
+        );
+        let manifest_path = manifest_dir.join("AndroidManifest.xml");
+        fs::write(&manifest_path, manifest)
+            .map_err(|e| NdkError::IoPathError(manifest_path.clone(), e))?;
 
-        Ok(UnalignedApk {
+        let mut aapt2 = self.build_tool(bin!("aapt2"))?;
+        aapt2
+            .arg("link")
+            .arg("-o")
+            .arg(&linked_apk)
+            .arg("--manifest")
+            .arg(manifest_path)
+            .arg("-I")
+            .arg(self.android_jar()?);
+        if !aapt2.status()?.success() {
+            return Err(NdkError::CmdFailed(Box::new(aapt2)));
+        }
+        Ok(linked_apk)
+    }
+
+    /// Links the manifest, resources and assets with `aapt2`, unless none of them
+    /// changed since the previous build
+    pub fn create_apk(&self) -> Result<UnalignedApk, NdkError> {
+        std::fs::create_dir_all(&self.build_dir)?;
+
+        // The fingerprint is only written back once the APK is signed, so that a
+        // failing build never leaves behind a fingerprint for partially updated outputs
+        let fingerprint_path = self.fingerprint_path();
+        let previous = Fingerprint::load(&fingerprint_path);
+        if fingerprint_path.exists() {
+            fs::remove_file(&fingerprint_path)
+                .map_err(|e| NdkError::IoPathError(fingerprint_path, e))?;
+        }
+
+        let fingerprint = Fingerprint {
+            link: self.link_fingerprint()?,
+            package: self.package_fingerprint()?,
+            ..Default::default()
+        };
+        let linked_apk = self.linked_apk();
+        if fingerprint.link != previous.link || !linked_apk.exists() {
+            self.link(&linked_apk, false)?;
+        }
+
+        let mut apk = UnalignedApk {
             config: self,
-            pending_libs: HashSet::default(),
+            pending_libs: BTreeSet::default(),
+            previous,
+            fingerprint,
+        };
+        if self.has_code() {
+            apk.add_code()?;
+        }
+        Ok(apk)
+    }
+
+    /// Output of `aapt2 link`, from which the APK is written
+    fn linked_apk(&self) -> PathBuf {
+        self.build_dir.join(format!("{}.linked.apk", self.apk_name))
+    }
+
+    /// Inputs of the previous build, see [`Fingerprint`]
+    fn fingerprint_path(&self) -> PathBuf {
+        self.build_dir
+            .join(format!("{}.fingerprint", self.apk_name))
+    }
+
+    fn link_fingerprint(&self) -> Result<String, NdkError> {
+        let mut hasher = InputHasher::default();
+        hasher.str(&quick_xml::se::to_string(&self.manifest)?);
+        hasher.path(&self.android_jar()?);
+        hasher.bytes(&[self.disable_aapt_compression as u8]);
+        for file in self.manifest.overlays.iter().chain(&self.aars) {
+            hasher.path(file);
+            hasher.file(file)?;
+        }
+        for dir in [&self.resources, &self.assets] {
+            match dir {
+                Some(dir) => {
+                    hasher.bytes(&[1]);
+                    hasher.dir(dir)?;
+                }
+                None => hasher.bytes(&[0]),
+            }
+        }
+        Ok(hasher.finish())
+    }
+
+    fn package_fingerprint(&self) -> Result<String, NdkError> {
+        let mut hasher = InputHasher::default();
+        hasher.bytes(&[
+            self.disable_aapt_compression as u8,
+            self.split_abis as u8,
+            self.reproducible as u8,
+        ]);
+        // The NDK and the `missing_libs` options decide whether the libraries pass the
+        // checks of `UnalignedApk::add_lib_recursively()`
+        hasher.path(self.ndk.ndk());
+        hasher.str(&format!("{:?}", self.missing_libs));
+        for lib in &self.allowed_missing_libs {
+            hasher.str(lib);
+        }
+        if let Some(timestamp) = self.reproducible_timestamp()? {
+            hasher.bytes(&timestamp.time.to_le_bytes());
+            hasher.bytes(&timestamp.date.to_le_bytes());
+        }
+        Ok(hasher.finish())
+    }
+}
+
+/// Groups `libs` by the split APK they are written to with [`ApkConfig::split_abis`]
+fn split_libs(libs: &BTreeSet<String>) -> Vec<(Target, Vec<&String>)> {
+    Target::ALL
+        .iter()
+        .map(|&target| {
+            let prefix = format!("lib/{}/", target.android_abi());
+            let libs = libs.iter().filter(|lib| lib.starts_with(&prefix)).collect();
+            (target, libs)
         })
+        .collect()
+}
+
+/// Name of the configuration split holding the native libraries of `target`, e.g.
+/// `config.arm64_v8a`
+fn split_name(target: Target) -> String {
+    format!("config.{}", target.android_abi().replace('-', "_"))
+}
+
+/// Lists resource files laid out as `<res>/<type>[-<qualifiers>]/<file>`, which is
+/// the only structure accepted by `aapt2 compile`
+fn list_resource_files(res: &Path) -> Result<Vec<PathBuf>, NdkError> {
+    let mut files = Vec::new();
+    for dir in fs::read_dir(res).map_err(|e| NdkError::IoPathError(res.to_owned(), e))? {
+        let dir = dir?.path();
+        if !dir.is_dir() {
+            continue;
+        }
+        for file in fs::read_dir(&dir).map_err(|e| NdkError::IoPathError(dir.clone(), e))? {
+            let file = file?.path();
+            if file.is_file() {
+                files.push(file);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Lists the `.flat` files that `aapt2 compile` wrote to `dir`
+fn list_flat_files(dir: &Path) -> Result<Vec<PathBuf>, NdkError> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|e| NdkError::IoPathError(dir.to_owned(), e))? {
+        let path = entry?.path();
+        if path.extension() == Some(OsStr::new("flat")) {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Flattens a resource path relative to the `resources` directory into a single
+/// directory name, e.g. `values-en/strings.xml` becomes `values-en_strings.xml`
+fn compiled_resource_dir_name(rel_path: &Path) -> String {
+    rel_path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Returns `true` when `out_dir` holds no compiled output for `source`, or when
+/// `source` was modified after it was last compiled
+fn needs_recompile(source: &Path, out_dir: &Path) -> Result<bool, NdkError> {
+    if !out_dir.exists() {
+        return Ok(true);
+    }
+    let source_modified = fs::metadata(source)?.modified()?;
+    let flat_files = list_flat_files(out_dir)?;
+    if flat_files.is_empty() {
+        return Ok(true);
+    }
+    for flat in flat_files {
+        if fs::metadata(&flat)?.modified()? < source_modified {
+            return Ok(true);
+        }
     }
+    Ok(false)
 }
 
 pub struct UnalignedApk<'a> {
-    config: &'a ApkConfig,
-    pending_libs: HashSet<String>,
+    pub(crate) config: &'a ApkConfig,
+    /// Native libraries and dex files in the build directory, named by their path
+    /// relative to it, that are yet to be added to the APK
+    pub(crate) pending_libs: BTreeSet<String>,
+    /// Inputs of the previous build
+    pub(crate) previous: Fingerprint,
+    /// Inputs of this build
+    pub(crate) fingerprint: Fingerprint,
 }
 
 impl<'a> UnalignedApk<'a> {
@@ -117,15 +576,48 @@ impl<'a> UnalignedApk<'a> {
         self.config
     }
 
+    /// Checks that the `PT_LOAD` segments of a 64-bit library are aligned to 16 KiB, as
+    /// devices with 16 KiB pages fail to load it otherwise. Misaligned libraries fail with
+    /// [`ApkConfig::page_size_16k`], and print a warning otherwise.
+    fn check_page_alignment(&self, path: &Path) -> Result<(), NdkError> {
+        let elf = Elf::read(path)?;
+        if let Some(warning) = page_alignment_warning(path, &elf, self.config.page_size_16k)? {
+            eprintln!("Warning: {warning}");
+        }
+        Ok(())
+    }
+
     pub fn add_lib(&mut self, path: &Path, target: Target) -> Result<(), NdkError> {
         if !path.exists() {
             return Err(NdkError::PathNotFound(path.into()));
         }
+        self.check_page_alignment(path)?;
         let abi = target.android_abi();
         let lib_path = Path::new("lib").join(abi).join(path.file_name().unwrap());
         let out = self.config.build_dir.join(&lib_path);
         std::fs::create_dir_all(out.parent().unwrap())?;
 
+        // Use UNIX path separators for the zip entry on non-UNIX systems, ensuring the resulting
+        // separator is compatible with the target device instead of the host platform.
+        // Otherwise, it results in a runtime error when loading the NativeActivity `.so` library.
+        let lib_path_unix = lib_path.to_str().unwrap().replace('\\', "/");
+
+        let mut hasher = InputHasher::default();
+        hasher.str(&format!("{:?}", self.config.strip));
+        hasher.file(path)?;
+        let hash = hasher.finish();
+
+        // Skip copying or stripping a library that didn't change since the previous build
+        let dwarf_path = out.with_extension("dwarf");
+        let unchanged = self.previous.libs.get(&lib_path_unix) == Some(&hash)
+            && out.exists()
+            && (self.config.strip == StripConfig::Default || dwarf_path.exists());
+        self.fingerprint.libs.insert(lib_path_unix.clone(), hash);
+        self.pending_libs.insert(lib_path_unix);
+        if unchanged {
+            return Ok(());
+        }
+
         match self.config.strip {
             StripConfig::Default => {
                 std::fs::copy(path, out)?;
@@ -144,20 +636,18 @@ impl<'a> UnalignedApk<'a> {
                     }
                 }
 
-                if self.config.strip == StripConfig::Split {
-                    let dwarf_path = out.with_extension("dwarf");
-
-                    {
-                        let mut cmd = Command::new(&obj_copy);
-                        cmd.arg("--only-keep-debug");
-                        cmd.arg(path);
-                        cmd.arg(&dwarf_path);
+                {
+                    let mut cmd = Command::new(&obj_copy);
+                    cmd.arg("--only-keep-debug");
+                    cmd.arg(path);
+                    cmd.arg(&dwarf_path);
 
-                        if !cmd.status()?.success() {
-                            return Err(NdkError::CmdFailed(Box::new(cmd)));
-                        }
+                    if !cmd.status()?.success() {
+                        return Err(NdkError::CmdFailed(Box::new(cmd)));
                     }
+                }
 
+                if self.config.strip == StripConfig::Split {
                     let mut cmd = Command::new(obj_copy);
                     cmd.arg(format!("--add-gnu-debuglink={}", dwarf_path.display()));
                     cmd.arg(out);
@@ -169,13 +659,6 @@ impl<'a> UnalignedApk<'a> {
             }
         }
 
-        // Pass UNIX path separators to `aapt` on non-UNIX systems, ensuring the resulting separator
-        // is compatible with the target device instead of the host platform.
-        // Otherwise, it results in a runtime error when loading the NativeActivity `.so` library.
-        let lib_path_unix = lib_path.to_str().unwrap().replace('\\', "/");
-
-        self.pending_libs.insert(lib_path_unix);
-
         Ok(())
     }
 
@@ -196,62 +679,334 @@ impl<'a> UnalignedApk<'a> {
         Ok(())
     }
 
+    /// Writes the APK from the output of `aapt2` with all pending libraries added to
+    /// it, aligning uncompressed entries on the fly.
+    ///
+    /// Stored entries are aligned to 4 bytes, except for uncompressed `.so` files
+    /// which are aligned to 16 KiB pages so that they can be loaded directly from
+    /// the APK on devices with either 4 KiB or 16 KiB pages.
+    ///
+    /// With [`ApkConfig::split_abis`], the libraries of every ABI are instead
+    /// written to their own configuration split APK.
+    ///
+    /// Stripped libraries also have their debug information written to
+    /// [`ApkConfig::native_debug_symbols`].
+    ///
+    /// Nothing is written when none of the inputs changed since the previous build.
     pub fn add_pending_libs_and_align(self) -> Result<UnsignedApk<'a>, NdkError> {
-        let mut aapt = self.config.build_tool(bin!("aapt"))?;
-        aapt.arg("add");
+        let config = self.config;
 
-        if self.config.disable_aapt_compression {
-            aapt.arg("-0").arg("");
+        let mut outputs = vec![config.apk()];
+        if config.split_abis {
+            outputs.extend(
+                split_libs(&self.pending_libs)
+                    .into_iter()
+                    .filter(|(_, libs)| !libs.is_empty())
+                    .map(|(target, _)| config.split_apk(target)),
+            );
         }
+        let up_to_date = self.fingerprint.link == self.previous.link
+            && self.fingerprint.package == self.previous.package
+            && self.fingerprint.libs == self.previous.libs
+            && outputs.iter().all(|output| output.exists())
+            && (config.strip == StripConfig::Default || config.native_debug_symbols().exists());
 
-        aapt.arg(self.config.unaligned_apk());
+        let mut unsigned = UnsignedApk {
+            config,
+            pending_libs: self.pending_libs,
+            previous: self.previous,
+            fingerprint: self.fingerprint,
+            written: false,
+        };
+        if !up_to_date {
+            unsigned.write()?;
+            config.write_native_debug_symbols(&unsigned.pending_libs)?;
+        }
+        Ok(unsigned)
+    }
+}
+
+impl ApkConfig {
+    /// Writes `output` from the APK at `linked` with `libs`, relative to the build
+    /// directory, added to it.
+    ///
+    /// Libraries for which `unchanged` returns `true` are copied as-is from the
+    /// previous `output`, if any, instead of being compressed again.
+    fn add_libs_and_align(
+        &self,
+        linked: &Path,
+        output: &Path,
+        libs: &[&String],
+        unchanged: &dyn Fn(&str) -> bool,
+    ) -> Result<(), NdkError> {
+        let linked_data = fs::read(linked).map_err(|e| NdkError::IoPathError(linked.into(), e))?;
+        let archive = ZipArchive::parse(&linked_data)?;
 
-        for lib_path_unix in self.pending_libs {
-            aapt.arg(lib_path_unix);
+        let previous_data = if output.exists() {
+            fs::read(output).map_err(|e| NdkError::IoPathError(output.into(), e))?
+        } else {
+            Vec::new()
+        };
+        let previous = ZipArchive::parse(&previous_data).ok();
+
+        let file = File::create(output).map_err(|e| NdkError::IoPathError(output.into(), e))?;
+        let mut zip = ZipWriter::new(BufWriter::new(file));
+
+        let timestamp = self.reproducible_timestamp()?;
+        for entry in entries_in_order(&archive, self.reproducible) {
+            if !libs.contains(&&entry.name) {
+                let entry = normalize_entry(entry, timestamp);
+                zip.copy_entry(&entry, archive.raw_data(&entry), alignment_for(&entry.name))?;
+            }
         }
 
-        if !aapt.status()?.success() {
-            return Err(NdkError::CmdFailed(Box::new(aapt)));
+        // Libraries that are not extracted on installation are mapped directly from the
+        // APK, which requires them to be stored uncompressed and page-aligned
+        let extract_native_libs = self.manifest.application.extract_native_libs != Some(false);
+        let compression = if self.disable_aapt_compression || !extract_native_libs {
+            Compression::Stored
+        } else {
+            Compression::Deflated
+        };
+
+        for lib_path_unix in libs {
+            let alignment = alignment_for(lib_path_unix);
+            let previous_entry = previous
+                .as_ref()
+                .filter(|_| unchanged(lib_path_unix))
+                .and_then(|previous| {
+                    let entry = previous
+                        .entries()
+                        .iter()
+                        .find(|e| e.name == **lib_path_unix)?;
+                    Some((entry, previous.raw_data(entry)))
+                });
+            if let Some((entry, raw)) = previous_entry {
+                zip.copy_entry(entry, raw, alignment)?;
+                continue;
+            }
+
+            let lib_path = self.build_dir.join(lib_path_unix);
+            let data =
+                fs::read(&lib_path).map_err(|e| NdkError::IoPathError(lib_path.clone(), e))?;
+            let modified = match timestamp {
+                Some(timestamp) => timestamp,
+                None => DosDateTime::from_system_time(fs::metadata(&lib_path)?.modified()?),
+            };
+            zip.add_entry(lib_path_unix, &data, compression, modified, alignment)?;
         }
 
-        let mut zipalign = self.config.build_tool(bin!("zipalign"))?;
-        zipalign
-            .arg("-f")
-            .arg("-v")
-            .arg("4")
-            .arg(self.config.unaligned_apk())
-            .arg(self.config.apk());
+        zip.finish()?;
+        Ok(())
+    }
 
-        if !zipalign.status()?.success() {
-            return Err(NdkError::CmdFailed(Box::new(zipalign)));
+    /// The timestamp given to every zip entry in [`ApkConfig::reproducible`] mode
+    pub(crate) fn reproducible_timestamp(&self) -> Result<Option<DosDateTime>, NdkError> {
+        if self.reproducible {
+            DosDateTime::from_source_date_epoch().map(Some)
+        } else {
+            Ok(None)
         }
+    }
+}
 
-        Ok(UnsignedApk(self.config))
+/// Returns the entries of `archive`, sorted by name in [`ApkConfig::reproducible`] mode
+pub(crate) fn entries_in_order<'b>(
+    archive: &'b ZipArchive<'_>,
+    reproducible: bool,
+) -> Vec<&'b ZipEntry> {
+    let mut entries = archive.entries().iter().collect::<Vec<_>>();
+    if reproducible {
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
     }
+    entries
 }
 
-pub struct UnsignedApk<'a>(&'a ApkConfig);
+/// Gives `entry` the fixed `timestamp` and no file permissions, if any
+pub(crate) fn normalize_entry(entry: &ZipEntry, timestamp: Option<DosDateTime>) -> ZipEntry {
+    let mut entry = entry.clone();
+    if let Some(timestamp) = timestamp {
+        entry.modified = timestamp;
+        entry.external_attributes = 0;
+    }
+    entry
+}
+
+pub struct UnsignedApk<'a> {
+    config: &'a ApkConfig,
+    pending_libs: BTreeSet<String>,
+    previous: Fingerprint,
+    fingerprint: Fingerprint,
+    /// Whether the APK was (re)written by this build
+    written: bool,
+}
 
 impl<'a> UnsignedApk<'a> {
-    pub fn sign(self, key: Key) -> Result<Apk, NdkError> {
-        let mut apksigner = self.0.build_tool(bat!("apksigner"))?;
-        apksigner
-            .arg("sign")
-            .arg("--ks")
-            .arg(&key.path)
-            .arg("--ks-pass")
-            .arg(format!("pass:{}", &key.password))
-            .arg(self.0.apk());
-        if !apksigner.status()?.success() {
-            return Err(NdkError::CmdFailed(Box::new(apksigner)));
-        }
-        Ok(Apk::from_config(self.0))
+    fn write(&mut self) -> Result<(), NdkError> {
+        let config = self.config;
+        let relink = self.fingerprint.link != self.previous.link;
+        let unchanged = |lib: &str| self.fingerprint.lib_unchanged(&self.previous, lib);
+
+        if !config.split_abis {
+            let libs = self.pending_libs.iter().collect::<Vec<_>>();
+            config.add_libs_and_align(&config.linked_apk(), &config.apk(), &libs, &unchanged)?;
+        } else {
+            let dex_files = self
+                .pending_libs
+                .iter()
+                .filter(|file| !file.starts_with("lib/"))
+                .collect::<Vec<_>>();
+            config.add_libs_and_align(
+                &config.linked_apk(),
+                &config.apk(),
+                &dex_files,
+                &unchanged,
+            )?;
+            for (target, libs) in split_libs(&self.pending_libs) {
+                let split_apk = config.split_apk(target);
+                if libs.is_empty() {
+                    // Don't leave splits of ABIs that are no longer built behind
+                    if split_apk.exists() {
+                        fs::remove_file(&split_apk)
+                            .map_err(|e| NdkError::IoPathError(split_apk, e))?;
+                    }
+                    continue;
+                }
+                let linked = config.link_split(target, relink)?;
+                config.add_libs_and_align(&linked, &split_apk, &libs, &unchanged)?;
+            }
+        }
+
+        self.written = true;
+        Ok(())
+    }
+
+    fn signing_fingerprint(&self, key: &Key) -> Result<String, NdkError> {
+        let mut hasher = InputHasher::default();
+        hasher.str(&format!("{:?}", self.config.signer));
+        hasher.bytes(&self.config.min_sdk_version().to_le_bytes());
+        // The password is left out, as the fingerprint is stored in the build directory.
+        // Keystores are encrypted with it, so changing it changes the keystore file too.
+        hasher.path(&key.path);
+        hasher.file(&key.path)?;
+        if let Some(certificate) = &key.certificate {
+            hasher.path(certificate);
+            hasher.file(certificate)?;
+        }
+        Ok(hasher.finish())
+    }
+
+    /// Signs the APK and its split APKs, unless neither they nor the key changed
+    /// since the previous build
+    pub fn sign(mut self, key: Key) -> Result<Apk, NdkError> {
+        self.fingerprint.signing = self.signing_fingerprint(&key)?;
+        let fingerprint_path = self.config.fingerprint_path();
+        if !self.written {
+            if self.fingerprint == self.previous {
+                self.fingerprint.save(&fingerprint_path)?;
+                return Ok(Apk::from_config(self.config));
+            }
+            // Only the key changed, start over from an unsigned APK
+            self.write()?;
+        }
+
+        let apk = Apk::from_config(self.config);
+        let apks = std::iter::once(&apk.path).chain(apk.splits.iter().map(|(_, path)| path));
+        match self.config.signer {
+            SignerBackend::Native => {
+                let min_sdk_version = self.config.min_sdk_version();
+                let key = SigningKey::load(&key)?;
+                for path in apks {
+                    sign_apk(path, &key, min_sdk_version)?;
+                }
+            }
+            SignerBackend::Apksigner => {
+                // `apksigner --ks` only reads keystores, also reject a PEM file with both
+                // the key and its certificate chain
+                let data =
+                    fs::read(&key.path).map_err(|e| NdkError::IoPathError(key.path.clone(), e))?;
+                if key.certificate.is_some() || crate::keystore::is_pem(&data) {
+                    return Err(NdkError::ApksignerPemKey);
+                }
+                // Pass the password through the environment rather than the command
+                // line, where it would be visible to other processes
+                const PASSWORD_ENV: &str = "NDK_BUILD_KEYSTORE_PASSWORD";
+                for path in apks {
+                    let mut apksigner = self.config.build_tool(bat!("apksigner"))?;
+                    apksigner
+                        .env(PASSWORD_ENV, &key.password)
+                        .arg("sign")
+                        .arg("--ks")
+                        .arg(&key.path)
+                        .arg("--ks-pass")
+                        .arg(format!("env:{PASSWORD_ENV}"))
+                        .arg(path);
+                    if !apksigner.status()?.success() {
+                        return Err(NdkError::CmdFailed(Box::new(apksigner)));
+                    }
+                }
+            }
+        }
+
+        self.fingerprint.save(&fingerprint_path)?;
+        Ok(apk)
+    }
+}
+
+/// Quotes `arg` for the device shell that `adb shell` passes its command line to
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// Makes a bare class name such as `MainActivity` relative to the package, like
+/// `android:name` in the manifest, as `am start -n` takes it as a fully qualified name
+fn component_class_name(name: &str) -> String {
+    if name.contains('.') {
+        name.to_string()
+    } else {
+        format!(".{name}")
     }
 }
 
+/// Number of values that follow the `am start` intent option `option`
+fn intent_option_values(option: &str) -> usize {
+    match option {
+        "--esn" => 1,
+        extra if extra.starts_with("--e") => 2,
+        "-a" | "-d" | "-t" | "-i" | "-c" | "-n" | "-f" | "-p" | "--user" => 1,
+        _ => 0,
+    }
+}
+
+/// Command line of `am start` for `activity` of `package`, quoted for the device shell as
+/// `adb shell` joins its arguments into a single command line. The intent has the `MAIN`
+/// action, unless `intent_args` sets one with `-a`.
+fn am_start_args(package: &str, activity: &str, intent_args: &[String]) -> Vec<String> {
+    let mut sets_action = false;
+    let mut args = intent_args.iter();
+    while let Some(arg) = args.next() {
+        sets_action |= arg == "-a";
+        // Skip the values of the option, which may themselves look like `-a`
+        args.by_ref().take(intent_option_values(arg)).for_each(drop);
+    }
+
+    let mut am = vec!["am".to_string(), "start".to_string()];
+    if !sets_action {
+        am.extend(["-a".to_string(), "android.intent.action.MAIN".to_string()]);
+    }
+    let component = format!("{}/{}", package, component_class_name(activity));
+    am.extend(["-n".to_string(), shell_quote(&component)]);
+    am.extend(intent_args.iter().map(|arg| shell_quote(arg)));
+    am
+}
+
 pub struct Apk {
     path: PathBuf,
+    /// Configuration split APKs with the native libraries of each ABI
+    splits: Vec<(Target, PathBuf)>,
     package_name: String,
+    /// Name of the activity that is started from the launcher, see [`ApkConfig::launcher_activity()`]
+    launcher_activity: Option<String>,
     ndk: Ndk,
     reverse_port_forward: HashMap<String, String>,
 }
@@ -259,9 +1014,20 @@ pub struct Apk {
 impl Apk {
     pub fn from_config(config: &ApkConfig) -> Self {
         let ndk = config.ndk.clone();
+        let splits = if config.split_abis {
+            Target::ALL
+                .iter()
+                .map(|&target| (target, config.split_apk(target)))
+                .filter(|(_, path)| path.exists())
+                .collect()
+        } else {
+            Vec::new()
+        };
         Self {
             path: config.apk(),
+            splits,
             package_name: config.manifest.package.clone(),
+            launcher_activity: config.launcher_activity(),
             ndk,
             reverse_port_forward: config.reverse_port_forward.clone(),
         }
@@ -282,25 +1048,61 @@ impl Apk {
         Ok(())
     }
 
+    /// Installs the APK, together with the split APKs matching the ABIs of the device
     pub fn install(&self, device_serial: Option<&str>) -> Result<(), NdkError> {
         let mut adb = self.ndk.adb(device_serial)?;
 
-        adb.arg("install").arg("-r").arg(&self.path);
+        if self.splits.is_empty() {
+            adb.arg("install").arg("-r").arg(&self.path);
+        } else {
+            let abis = self.ndk.detect_abis(device_serial)?;
+            let splits = self
+                .splits
+                .iter()
+                .filter(|(target, _)| abis.contains(target))
+                .map(|(_, path)| path)
+                .collect::<Vec<_>>();
+            if splits.is_empty() {
+                let abis = abis.iter().map(|t| t.android_abi()).collect::<Vec<_>>();
+                return Err(NdkError::NoSplitForDeviceAbis(abis.join(", ")));
+            }
+            adb.arg("install-multiple")
+                .arg("-r")
+                .arg(&self.path)
+                .args(splits);
+        }
         if !adb.status()?.success() {
             return Err(NdkError::CmdFailed(Box::new(adb)));
         }
         Ok(())
     }
 
+    /// Name of the activity that is started from the launcher, if any
+    pub fn launcher_activity(&self) -> Option<&str> {
+        self.launcher_activity.as_deref()
+    }
+
     pub fn start(&self, device_serial: Option<&str>) -> Result<(), NdkError> {
+        self.start_activity(device_serial, None, &[])
+    }
+
+    /// Starts `activity`, or the [launcher activity](Apk::launcher_activity()) when `None`.
+    ///
+    /// `intent_args` are passed on to `am start` to add extras and data to the intent, such as
+    /// `--es key value` or `-d https://example.com`. The intent has the `MAIN` action, unless
+    /// `intent_args` sets one with `-a`.
+    pub fn start_activity(
+        &self,
+        device_serial: Option<&str>,
+        activity: Option<&str>,
+        intent_args: &[String],
+    ) -> Result<(), NdkError> {
+        let activity = activity
+            .or(self.launcher_activity.as_deref())
+            .ok_or(NdkError::NoLauncherActivity)?;
         let mut adb = self.ndk.adb(device_serial)?;
         adb.arg("shell")
-            .arg("am")
-            .arg("start")
-            .arg("-a")
-            .arg("android.intent.action.MAIN")
-            .arg("-n")
-            .arg(format!("{}/android.app.NativeActivity", self.package_name));
+            .args(am_start_args(&self.package_name, activity, intent_args));
 
         if !adb.status()?.success() {
             return Err(NdkError::CmdFailed(Box::new(adb)));
@@ -341,3 +1143,218 @@ impl Apk {
             .map_err(|e| NdkError::NotAUid(e, uid.to_owned()))
     }
 }
+
+/// Returns a warning for the 64-bit library `elf` at `path` when its `PT_LOAD` segments
+/// are aligned to less than 16 KiB, or fails with [`NdkError::UnalignedLoadSegments`] when
+/// `page_size_16k` is enabled, as the library then wasn't linked by [`cargo_ndk()`]
+///
+/// [`cargo_ndk()`]: crate::cargo::cargo_ndk()
+fn page_alignment_warning(
+    path: &Path,
+    elf: &Elf,
+    page_size_16k: bool,
+) -> Result<Option<String>, NdkError> {
+    let alignment = match elf.page_alignment() {
+        Some(alignment) if elf.is_64 && alignment < u64::from(PAGE_ALIGNMENT) => alignment,
+        _ => return Ok(None),
+    };
+    if page_size_16k {
+        return Err(NdkError::UnalignedLoadSegments(path.into(), alignment));
+    }
+    Ok(Some(format!(
+        "`{path:?}` has LOAD segments aligned to {alignment} bytes and fails to load on devices with 16 KiB pages. Enable `page_size_16k` to link with `-Wl,-z,max-page-size=16384`, and relink prebuilt libraries with that flag (NDK r28 and newer do so by default)"
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf::LoadSegment;
+    use crate::zip::DEFAULT_ALIGNMENT;
+
+    #[test]
+    fn compiled_resource_dir_name_is_flat() {
+        assert_eq!(
+            compiled_resource_dir_name(Path::new("values-en").join("strings.xml").as_path()),
+            "values-en_strings.xml"
+        );
+        assert_eq!(
+            compiled_resource_dir_name(Path::new("mipmap-hdpi/ic_launcher.png")),
+            "mipmap-hdpi_ic_launcher.png"
+        );
+    }
+
+    #[test]
+    fn splits_libs_by_abi() {
+        let libs = [
+            "lib/arm64-v8a/libmain.so",
+            "lib/arm64-v8a/libc++_shared.so",
+            "lib/x86_64/libmain.so",
+        ]
+        .iter()
+        .map(|lib| lib.to_string())
+        .collect::<BTreeSet<_>>();
+        let splits = split_libs(&libs)
+            .into_iter()
+            .map(|(target, libs)| (split_name(target), libs))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            splits,
+            [
+                ("config.armeabi_v7a".to_string(), Vec::<&String>::new()),
+                (
+                    "config.arm64_v8a".to_string(),
+                    vec![
+                        &"lib/arm64-v8a/libc++_shared.so".to_string(),
+                        &"lib/arm64-v8a/libmain.so".to_string()
+                    ]
+                ),
+                ("config.x86".to_string(), vec![]),
+                (
+                    "config.x86_64".to_string(),
+                    vec![&"lib/x86_64/libmain.so".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reproducible_output_is_identical() {
+        // Writes the entries of a linked APK like `ApkConfig::add_libs_and_align()`,
+        // from archives that only differ in entry order and timestamps
+        fn write(names: &[&str], modified: DosDateTime, reproducible: bool) -> Vec<u8> {
+            let mut linked = ZipWriter::new(Vec::new());
+            for name in names {
+                linked
+                    .add_entry(
+                        name,
+                        name.as_bytes(),
+                        Compression::Deflated,
+                        modified,
+                        DEFAULT_ALIGNMENT,
+                    )
+                    .unwrap();
+            }
+            let linked = linked.finish().unwrap();
+            let archive = ZipArchive::parse(&linked).unwrap();
+
+            let timestamp = Some(DosDateTime::EPOCH).filter(|_| reproducible);
+            let mut zip = ZipWriter::new(Vec::new());
+            for entry in entries_in_order(&archive, reproducible) {
+                let entry = normalize_entry(entry, timestamp);
+                zip.copy_entry(&entry, archive.raw_data(&entry), alignment_for(&entry.name))
+                    .unwrap();
+            }
+            let lib = "lib/arm64-v8a/libmain.so";
+            zip.add_entry(
+                lib,
+                &[0x7f; 100],
+                Compression::Stored,
+                timestamp.unwrap_or(modified),
+                alignment_for(lib),
+            )
+            .unwrap();
+            zip.finish().unwrap()
+        }
+
+        let first = [
+            "AndroidManifest.xml",
+            "resources.arsc",
+            "res/layout/main.xml",
+        ];
+        let second = [
+            "res/layout/main.xml",
+            "AndroidManifest.xml",
+            "resources.arsc",
+        ];
+        let later = DosDateTime::from_unix_timestamp(1701347696);
+        assert_eq!(
+            write(&first, DosDateTime::EPOCH, true),
+            write(&second, later, true)
+        );
+        assert_ne!(
+            write(&first, DosDateTime::EPOCH, false),
+            write(&second, later, false)
+        );
+    }
+
+    #[test]
+    fn quotes_for_device_shell() {
+        assert_eq!(shell_quote("Foo$Bar"), "'Foo$Bar'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn am_start_command_line() {
+        let args = |intent_args: &[&str]| {
+            let intent_args = intent_args
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect::<Vec<_>>();
+            am_start_args("com.example.app", "Main$Inner", &intent_args).join(" ")
+        };
+        assert_eq!(
+            args(&[]),
+            "am start -a android.intent.action.MAIN -n 'com.example.app/.Main$Inner'"
+        );
+        assert_eq!(
+            am_start_args("com.example.app", "android.app.NativeActivity", &[])[5],
+            "'com.example.app/android.app.NativeActivity'"
+        );
+        assert_eq!(
+            args(&["-a", "android.intent.action.VIEW", "-d", "https://example.com"]),
+            "am start -n 'com.example.app/.Main$Inner' '-a' 'android.intent.action.VIEW' '-d' 'https://example.com'"
+        );
+        // A `-a` value of an extra is not an action
+        assert_eq!(
+            args(&["--es", "flag", "-a", "--ez", "verbose", "true"]),
+            "am start -a android.intent.action.MAIN -n 'com.example.app/.Main$Inner' '--es' 'flag' '-a' '--ez' 'verbose' 'true'"
+        );
+        assert_eq!(
+            args(&["-d", "-a"]),
+            "am start -a android.intent.action.MAIN -n 'com.example.app/.Main$Inner' '-d' '-a'"
+        );
+    }
+
+    #[test]
+    fn page_alignment() {
+        let path = Path::new("libprebuilt.so");
+        let segment = |align| LoadSegment {
+            offset: 0,
+            vaddr: 0,
+            file_size: 0x1000,
+            mem_size: 0x1000,
+            align,
+        };
+        let elf = |is_64, aligns: &[u64]| Elf {
+            is_64,
+            load_segments: aligns.iter().map(|&align| segment(align)).collect(),
+            ..Default::default()
+        };
+
+        let aligned = elf(true, &[0x4000, 0x10000]);
+        assert!(page_alignment_warning(path, &aligned, true)
+            .unwrap()
+            .is_none());
+        // 32-bit ABIs keep 4 KiB pages
+        assert!(page_alignment_warning(path, &elf(false, &[0x1000]), true)
+            .unwrap()
+            .is_none());
+
+        let misaligned = elf(true, &[0x4000, 0x1000]);
+        let warning = page_alignment_warning(path, &misaligned, false)
+            .unwrap()
+            .unwrap();
+        assert!(warning.contains("aligned to 4096 bytes"), "{}", warning);
+        assert!(warning.contains("Enable `page_size_16k`"), "{}", warning);
+        match page_alignment_warning(path, &misaligned, true) {
+            Err(error @ NdkError::UnalignedLoadSegments(..)) => {
+                let message = error.to_string();
+                assert!(message.contains("relink this library"), "{}", message);
+                assert!(!message.contains("Enable `page_size_16k`"), "{}", message);
+            }
+            result => panic!("expected misaligned segments, got {:?}", result),
+        }
+    }
+}
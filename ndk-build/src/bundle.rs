@@ -0,0 +1,224 @@
+//! Android App Bundle (`.aab`) packaging.
+//!
+//! A bundle holds a single `base` module, laid out as:
+//!
+//! - `base/manifest/AndroidManifest.xml`, in protobuf format;
+//! - `base/resources.pb` and `base/res/`, the protobuf resource table and compiled resources;
+//! - `base/assets/`;
+//! - `base/lib/<abi>/`, the native libraries;
+//! - `base/root/`, any other file that ends up at the root of the generated APKs;
+//!
+//! and a `BundleConfig.pb` at the root of the archive. See
+//! <https://developer.android.com/guide/app-bundle/app-bundle-format>.
+
+use crate::apk::{entries_in_order, normalize_entry, ApkConfig, UnalignedApk};
+use crate::dex::is_dex_file;
+use crate::error::NdkError;
+use crate::fingerprint::Fingerprint;
+use crate::keystore::SigningKey;
+use crate::ndk::Key;
+use crate::sign::sign_bundle;
+use crate::target::Target;
+use crate::zip::{alignment_for, Compression, DosDateTime, ZipArchive, ZipWriter};
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// The `bundletool` release whose `BundleConfig.pb` format is written, which
+/// `bundletool` uses to detect bundles built for a newer version of itself
+const BUNDLETOOL_VERSION: &str = "1.15.6";
+
+impl ApkConfig {
+    /// Retrieves the path of the bundle that will be written when
+    /// [`UnsignedBundle::sign`] is invoked
+    #[inline]
+    pub fn bundle(&self) -> PathBuf {
+        self.build_dir.join(format!("{}.aab", self.apk_name))
+    }
+
+    /// Intermediate APK with the manifest and resources linked in protobuf format
+    fn proto_apk(&self) -> PathBuf {
+        self.build_dir.join(format!("{}.proto.apk", self.apk_name))
+    }
+
+    pub fn create_bundle(&self) -> Result<AppBundle<'_>, NdkError> {
+        self.link(&self.proto_apk(), true)?;
+
+        let mut libs = UnalignedApk {
+            config: self,
+            pending_libs: BTreeSet::default(),
+            previous: Fingerprint::default(),
+            fingerprint: Fingerprint::default(),
+        };
+        if self.has_code() {
+            libs.add_code()?;
+        }
+        Ok(AppBundle { libs })
+    }
+}
+
+/// An App Bundle whose native libraries are still being collected
+pub struct AppBundle<'a> {
+    libs: UnalignedApk<'a>,
+}
+
+impl<'a> AppBundle<'a> {
+    pub fn config(&self) -> &ApkConfig {
+        self.libs.config()
+    }
+
+    pub fn add_lib(&mut self, path: &Path, target: Target) -> Result<(), NdkError> {
+        self.libs.add_lib(path, target)
+    }
+
+    pub fn add_lib_recursively(
+        &mut self,
+        lib: &Path,
+        target: Target,
+        search_paths: &[&Path],
+    ) -> Result<(), NdkError> {
+        self.libs.add_lib_recursively(lib, target, search_paths)
+    }
+
+    pub fn add_aar_libs(&mut self, target: Target, search_paths: &[&Path]) -> Result<(), NdkError> {
+        self.libs.add_aar_libs(target, search_paths)
+    }
+
+    pub fn add_runtime_libs(
+        &mut self,
+        path: &Path,
+        target: Target,
+        search_paths: &[&Path],
+    ) -> Result<(), NdkError> {
+        self.libs.add_runtime_libs(path, target, search_paths)
+    }
+
+    /// Writes the bundle, moving the output of `aapt2` into the `base` module and
+    /// adding all pending libraries and the `BundleConfig.pb`. Stripped libraries also
+    /// have their debug information written to [`ApkConfig::native_debug_symbols`].
+    pub fn add_pending_libs(self) -> Result<UnsignedBundle<'a>, NdkError> {
+        let config = self.libs.config;
+        let proto_apk = config.proto_apk();
+        let linked =
+            fs::read(&proto_apk).map_err(|e| NdkError::IoPathError(proto_apk.clone(), e))?;
+        let archive = ZipArchive::parse(&linked)?;
+
+        let bundle_path = config.bundle();
+        let file = File::create(&bundle_path).map_err(|e| NdkError::IoPathError(bundle_path, e))?;
+        let mut zip = ZipWriter::new(BufWriter::new(file));
+
+        zip.add_entry(
+            "BundleConfig.pb",
+            &bundle_config(),
+            Compression::Deflated,
+            DosDateTime::EPOCH,
+            alignment_for("BundleConfig.pb"),
+        )?;
+
+        let timestamp = config.reproducible_timestamp()?;
+        for entry in entries_in_order(&archive, config.reproducible) {
+            let name = base_module_path(&entry.name);
+            let entry = normalize_entry(entry, timestamp);
+            zip.copy_entry_as(
+                &name,
+                &entry,
+                archive.raw_data(&entry),
+                alignment_for(&name),
+            )?;
+        }
+
+        for lib_path_unix in &self.libs.pending_libs {
+            let lib_path = config.build_dir.join(lib_path_unix);
+            let data =
+                fs::read(&lib_path).map_err(|e| NdkError::IoPathError(lib_path.clone(), e))?;
+            let modified = match timestamp {
+                Some(timestamp) => timestamp,
+                None => DosDateTime::from_system_time(fs::metadata(&lib_path)?.modified()?),
+            };
+            let name = base_module_path(lib_path_unix);
+            zip.add_entry(
+                &name,
+                &data,
+                Compression::Deflated,
+                modified,
+                alignment_for(&name),
+            )?;
+        }
+
+        zip.finish()?;
+        config.write_native_debug_symbols(&self.libs.pending_libs)?;
+
+        Ok(UnsignedBundle(config))
+    }
+}
+
+pub struct UnsignedBundle<'a>(&'a ApkConfig);
+
+impl<'a> UnsignedBundle<'a> {
+    /// Signs the bundle with a v1 JAR signature and returns its path.
+    ///
+    /// Bundles are always signed natively, regardless of [`ApkConfig::signer`], as
+    /// `apksigner` does not support them.
+    pub fn sign(self, key: Key) -> Result<PathBuf, NdkError> {
+        let bundle = self.0.bundle();
+        sign_bundle(&bundle, &SigningKey::load(&key)?)?;
+        Ok(bundle)
+    }
+}
+
+/// Maps a path inside an APK linked by `aapt2` to its location in the `base` module
+fn base_module_path(name: &str) -> String {
+    match name {
+        "AndroidManifest.xml" => "base/manifest/AndroidManifest.xml".to_string(),
+        "resources.pb" => "base/resources.pb".to_string(),
+        _ if is_dex_file(name) => format!("base/dex/{name}"),
+        _ if ["res/", "assets/", "lib/"]
+            .iter()
+            .any(|dir| name.starts_with(dir)) =>
+        {
+            format!("base/{name}")
+        }
+        _ => format!("base/root/{name}"),
+    }
+}
+
+/// Encodes a `BundleConfig` protobuf message that only records the `bundletool`
+/// version, leaving all optimizations at their defaults
+fn bundle_config() -> Vec<u8> {
+    // message Bundletool { string version = 2; }
+    let mut bundletool = vec![0x12, BUNDLETOOL_VERSION.len() as u8];
+    bundletool.extend_from_slice(BUNDLETOOL_VERSION.as_bytes());
+    // message BundleConfig { Bundletool bundletool = 1; ... }
+    let mut config = vec![0x0a, bundletool.len() as u8];
+    config.extend_from_slice(&bundletool);
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_module_layout() {
+        assert_eq!(
+            base_module_path("AndroidManifest.xml"),
+            "base/manifest/AndroidManifest.xml"
+        );
+        assert_eq!(base_module_path("resources.pb"), "base/resources.pb");
+        assert_eq!(
+            base_module_path("res/mipmap-hdpi-v4/icon.png"),
+            "base/res/mipmap-hdpi-v4/icon.png"
+        );
+        assert_eq!(base_module_path("assets/a.txt"), "base/assets/a.txt");
+        assert_eq!(base_module_path("classes2.dex"), "base/dex/classes2.dex");
+        assert_eq!(
+            base_module_path("lib/arm64-v8a/libfoo.so"),
+            "base/lib/arm64-v8a/libfoo.so"
+        );
+        assert_eq!(
+            base_module_path("kotlin/x.kotlin_builtins"),
+            "base/root/kotlin/x.kotlin_builtins"
+        );
+    }
+}
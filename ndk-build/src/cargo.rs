@@ -4,18 +4,23 @@ use crate::target::Target;
 use std::path::Path;
 use std::process::Command;
 
+/// Separator of the flags in `CARGO_ENCODED_RUSTFLAGS`
+const SEP: &str = "\x1f";
+
+/// Creates a `cargo` command that builds for `target` with the NDK toolchain. With
+/// `page_size_16k`, libraries are linked with 16 KiB aligned segments for devices with
+/// 16 KiB pages.
 pub fn cargo_ndk(
     ndk: &Ndk,
     target: Target,
     sdk_version: u32,
     target_dir: impl AsRef<Path>,
+    page_size_16k: bool,
 ) -> Result<Command, NdkError> {
     let triple = target.rust_triple();
     let clang_target = format!("--target={}{}", target.ndk_llvm_triple(), sdk_version);
     let mut cargo = Command::new("cargo");
 
-    const SEP: &str = "\x1f";
-
     // Read initial CARGO_ENCODED_/RUSTFLAGS
     let mut rustflags = match std::env::var("CARGO_ENCODED_RUSTFLAGS") {
         Ok(val) => {
@@ -63,11 +68,7 @@ pub fn cargo_ndk(
     // Configure LINKER for `rustc`
     // https://doc.rust-lang.org/beta/cargo/reference/environment-variables.html#configuration-environment-variables
     cargo.env(cargo_env_target_cfg("LINKER", triple), &clang);
-    if !rustflags.is_empty() {
-        rustflags.push_str(SEP);
-    }
-    rustflags.push_str("-Clink-arg=");
-    rustflags.push_str(&clang_target);
+    push_link_args(&mut rustflags, &clang_target, page_size_16k);
 
     let ar = ndk.toolchain_bin("ar", target)?;
     cargo.env(format!("AR_{triple}"), &ar);
@@ -110,6 +111,19 @@ pub fn cargo_ndk(
     Ok(cargo)
 }
 
+/// Appends the linker arguments for the NDK to the encoded `rustflags`
+fn push_link_args(rustflags: &mut String, clang_target: &str, page_size_16k: bool) {
+    if !rustflags.is_empty() {
+        rustflags.push_str(SEP);
+    }
+    rustflags.push_str("-Clink-arg=");
+    rustflags.push_str(clang_target);
+    if page_size_16k {
+        rustflags.push_str(SEP);
+        rustflags.push_str("-Clink-arg=-Wl,-z,max-page-size=16384");
+    }
+}
+
 fn cargo_env_target_cfg(tool: &str, target: &str) -> String {
     let utarget = target.replace('-', "_");
     let env = format!("CARGO_TARGET_{}_{}", &utarget, tool);
@@ -162,4 +176,18 @@ mod tests {
         let v = VersionCode::from_semver("254.254.254-alpha.fix+2").unwrap();
         assert_eq!(v, VersionCode::new(254, 254, 254));
     }
+
+    #[test]
+    fn link_args() {
+        let mut rustflags = String::new();
+        push_link_args(&mut rustflags, "--target=aarch64-linux-android23", false);
+        assert_eq!(rustflags, "-Clink-arg=--target=aarch64-linux-android23");
+
+        let mut rustflags = "-Cdebuginfo=1".to_string();
+        push_link_args(&mut rustflags, "--target=aarch64-linux-android23", true);
+        assert_eq!(
+            rustflags,
+            "-Cdebuginfo=1\x1f-Clink-arg=--target=aarch64-linux-android23\x1f-Clink-arg=-Wl,-z,max-page-size=16384"
+        );
+    }
 }
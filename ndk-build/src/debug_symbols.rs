@@ -0,0 +1,173 @@
+//! Native debug symbols archive, as uploaded to the Play Console and crash reporters.
+//!
+//! When libraries are stripped, the debug information that [`UnalignedApk::add_lib`]
+//! split off into `lib/<abi>/<lib>.dwarf` is collected into
+//! `native-debug-symbols.zip`, laid out as `<abi>/<lib>.so.dbg`. It is accompanied
+//! by `native-debug-symbols.txt`, which maps the GNU build-id of every library to
+//! its debug file in the archive, one `<build-id> <abi>/<lib>.so.dbg` per line.
+//!
+//! [`UnalignedApk::add_lib`]: crate::apk::UnalignedApk::add_lib
+
+use crate::apk::{ApkConfig, StripConfig};
+use crate::elf::Elf;
+use crate::error::NdkError;
+use crate::zip::{Compression, DosDateTime, ZipWriter, DEFAULT_ALIGNMENT};
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+impl ApkConfig {
+    /// Retrieves the path of the native debug symbols archive, which is written
+    /// alongside the APK or bundle unless [`ApkConfig::strip`] is
+    /// [`StripConfig::Default`]
+    #[inline]
+    pub fn native_debug_symbols(&self) -> PathBuf {
+        self.build_dir.join("native-debug-symbols.zip")
+    }
+
+    /// Retrieves the path of the build-id index of [`ApkConfig::native_debug_symbols`]
+    #[inline]
+    pub fn native_debug_symbols_index(&self) -> PathBuf {
+        self.build_dir.join("native-debug-symbols.txt")
+    }
+
+    /// Writes the native debug symbols archive and its index for the stripped
+    /// libraries among `libs`, relative to the build directory
+    pub(crate) fn write_native_debug_symbols(
+        &self,
+        libs: &BTreeSet<String>,
+    ) -> Result<(), NdkError> {
+        if self.strip == StripConfig::Default {
+            return Ok(());
+        }
+
+        let archive = self.native_debug_symbols();
+        let file = File::create(&archive).map_err(|e| NdkError::IoPathError(archive, e))?;
+        let mut zip = ZipWriter::new(BufWriter::new(file));
+        let mut index = String::new();
+
+        let timestamp = self.reproducible_timestamp()?;
+        for lib_path_unix in libs {
+            let name = match debug_file_name(lib_path_unix) {
+                Some(name) => name,
+                None => continue,
+            };
+            let lib_path = self.build_dir.join(lib_path_unix);
+            let dwarf_path = lib_path.with_extension("dwarf");
+            let data =
+                fs::read(&dwarf_path).map_err(|e| NdkError::IoPathError(dwarf_path.clone(), e))?;
+
+            match matching_build_id(&lib_path, &dwarf_path, &data)? {
+                Some(build_id) => index.push_str(&format!("{build_id} {name}\n")),
+                // Crash reporters can't match the debug file to the library without it
+                None => eprintln!(
+                    "Warning: {} has no GNU build-id and is left out of {}, link it with `-Wl,--build-id`",
+                    lib_path.display(),
+                    self.native_debug_symbols_index().display()
+                ),
+            }
+
+            let modified = match timestamp {
+                Some(timestamp) => timestamp,
+                None => DosDateTime::from_system_time(fs::metadata(&dwarf_path)?.modified()?),
+            };
+            zip.add_entry(
+                &name,
+                &data,
+                Compression::Deflated,
+                modified,
+                DEFAULT_ALIGNMENT,
+            )?;
+        }
+        zip.finish()?;
+
+        let index_path = self.native_debug_symbols_index();
+        fs::write(&index_path, index).map_err(|e| NdkError::IoPathError(index_path, e))?;
+        Ok(())
+    }
+}
+
+/// Maps `lib/<abi>/<lib>.so` to its entry `<abi>/<lib>.so.dbg` in the debug symbols
+/// archive, or returns [`None`] for anything that isn't a native library
+fn debug_file_name(lib_path_unix: &str) -> Option<String> {
+    let lib = lib_path_unix.strip_prefix("lib/")?;
+    lib.ends_with(".so").then(|| format!("{lib}.dbg"))
+}
+
+/// Returns the hex GNU build-id shared by the stripped library at `lib_path` and the
+/// debug file at `dwarf_path`, whose contents are `dwarf`. Fails when they differ, as
+/// the debug file then belongs to another build of the library.
+fn matching_build_id(
+    lib_path: &Path,
+    dwarf_path: &Path,
+    dwarf: &[u8],
+) -> Result<Option<String>, NdkError> {
+    let lib = Elf::read(lib_path)?;
+    let debug = Elf::parse(dwarf)
+        .map_err(|e| NdkError::InvalidElf(format!("{}: {e}", dwarf_path.display())))?;
+    if lib.build_id != debug.build_id {
+        return Err(NdkError::BuildIdMismatch {
+            library: lib_path.into(),
+            library_build_id: lib.build_id_hex(),
+            debug_file: dwarf_path.into(),
+            debug_build_id: debug.build_id_hex(),
+        });
+    }
+    Ok(lib.build_id_hex())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf::tests::library;
+
+    #[test]
+    fn debug_file_names() {
+        assert_eq!(
+            debug_file_name("lib/arm64-v8a/libmain.so").as_deref(),
+            Some("arm64-v8a/libmain.so.dbg")
+        );
+        assert_eq!(debug_file_name("classes.dex"), None);
+        assert_eq!(debug_file_name("lib/x86_64/libmain.dwarf"), None);
+    }
+
+    #[test]
+    fn matches_build_ids() {
+        let dir = std::env::temp_dir().join(format!("ndk-build-build-id-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let lib_path = dir.join("libmain.so");
+        let dwarf_path = dir.join("libmain.dwarf");
+        fs::write(&lib_path, library(&[], &[], &[], &[0xde, 0xad, 0xbe, 0xef])).unwrap();
+
+        let dwarf = library(&[], &[], &[], &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(
+            matching_build_id(&lib_path, &dwarf_path, &dwarf)
+                .unwrap()
+                .as_deref(),
+            Some("deadbeef")
+        );
+
+        let dwarf = library(&[], &[], &[], &[0xca, 0xfe]);
+        match matching_build_id(&lib_path, &dwarf_path, &dwarf) {
+            Err(NdkError::BuildIdMismatch {
+                library,
+                library_build_id,
+                debug_file,
+                debug_build_id,
+            }) => {
+                assert_eq!(library, lib_path);
+                assert_eq!(library_build_id.as_deref(), Some("deadbeef"));
+                assert_eq!(debug_file, dwarf_path);
+                assert_eq!(debug_build_id.as_deref(), Some("cafe"));
+            }
+            result => panic!("expected a build-id mismatch, got {:?}", result),
+        }
+
+        assert!(matches!(
+            matching_build_id(&lib_path, &dwarf_path, b"not an elf"),
+            Err(NdkError::InvalidElf(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
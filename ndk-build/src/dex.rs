@@ -0,0 +1,265 @@
+//! Compilation of Java and Kotlin sources and prebuilt JARs into `classes.dex`.
+
+use crate::apk::{ApkConfig, UnalignedApk};
+use crate::error::NdkError;
+use crate::fingerprint::{list_files_recursively, InputHasher};
+use crate::zip::{Compression, DosDateTime, ZipWriter};
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+impl ApkConfig {
+    /// Whether the APK contains code from [`ApkConfig::java_sources`],
+    /// [`ApkConfig::jars`] or [`ApkConfig::aars`]
+    pub fn has_code(&self) -> bool {
+        self.java_sources.is_some() || !self.jars.is_empty() || !self.aars.is_empty()
+    }
+
+    /// Directory of the `R` classes generated by `aapt2` for the resources of
+    /// [`ApkConfig::aars`], which are compiled along with the sources
+    pub(crate) fn generated_sources_dir(&self) -> PathBuf {
+        self.build_dir.join("gen")
+    }
+
+    /// [`ApkConfig::jars`] followed by the JARs of every AAR
+    fn all_jars(&self) -> Result<Vec<PathBuf>, NdkError> {
+        let mut jars = self.jars.clone();
+        for aar in self.extract_aars()? {
+            jars.extend(aar.jars()?);
+        }
+        Ok(jars)
+    }
+
+    fn code_fingerprint(&self) -> Result<String, NdkError> {
+        let mut hasher = InputHasher::default();
+        hasher.path(&self.android_jar()?);
+        hasher.bytes(&self.min_sdk_version().to_le_bytes());
+        hasher.bytes(&[self.manifest.application.debuggable.unwrap_or(false) as u8]);
+        if let Some(java_sources) = &self.java_sources {
+            hasher.dir(java_sources)?;
+        }
+        let generated = self.generated_sources_dir();
+        if generated.exists() {
+            hasher.dir(&generated)?;
+        }
+        for jar in &self.all_jars()? {
+            hasher.path(jar);
+            hasher.file(jar)?;
+        }
+        Ok(hasher.finish())
+    }
+
+    /// Compiles [`ApkConfig::java_sources`] and the generated `R` classes against
+    /// `android.jar` and all JARs, and converts the resulting classes together with
+    /// the JARs to dex files in the build directory with `d8`.
+    ///
+    /// Kotlin sources are compiled first with `kotlinc`, so that Java sources can
+    /// refer to them.
+    fn compile_code(&self) -> Result<(), NdkError> {
+        let classes_dir = self.build_dir.join("classes");
+        if classes_dir.exists() {
+            fs::remove_dir_all(&classes_dir)
+                .map_err(|e| NdkError::IoPathError(classes_dir.clone(), e))?;
+        }
+        fs::create_dir_all(&classes_dir)?;
+        for dex in list_dex_files(&self.build_dir)? {
+            fs::remove_file(&dex).map_err(|e| NdkError::IoPathError(dex, e))?;
+        }
+
+        let android_jar = self.android_jar()?;
+        let jars = self.all_jars()?;
+        let mut program = jars.clone();
+
+        let has_extension = |path: &PathBuf, ext| path.extension() == Some(OsStr::new(ext));
+        let is_source = |path: &PathBuf| has_extension(path, "java") || has_extension(path, "kt");
+        let mut sources = Vec::new();
+        if let Some(java_sources) = &self.java_sources {
+            list_files_recursively(java_sources, &mut sources)?;
+            if !sources.iter().any(is_source) {
+                return Err(NdkError::NoJavaSources(java_sources.clone()));
+            }
+        }
+        let generated = self.generated_sources_dir();
+        if generated.exists() {
+            list_files_recursively(&generated, &mut sources)?;
+        }
+        sources.sort();
+
+        if !sources.is_empty() {
+            let java = sources
+                .iter()
+                .filter(|path| has_extension(path, "java"))
+                .collect::<Vec<_>>();
+            let kotlin = sources
+                .iter()
+                .filter(|path| has_extension(path, "kt"))
+                .collect::<Vec<_>>();
+
+            let mut classpath = vec![android_jar.clone()];
+            classpath.extend(jars.iter().cloned());
+            let classpath = join_paths(&classpath)?;
+
+            if !kotlin.is_empty() {
+                let mut kotlinc = self.ndk.kotlinc()?;
+                kotlinc
+                    .arg("-classpath")
+                    .arg(&classpath)
+                    .arg("-jvm-target")
+                    .arg("1.8")
+                    .arg("-d")
+                    .arg(&classes_dir)
+                    .args(&kotlin)
+                    .args(&java);
+                if !kotlinc.status()?.success() {
+                    return Err(NdkError::CmdFailed(Box::new(kotlinc)));
+                }
+            }
+
+            if !java.is_empty() {
+                let mut classpath = vec![classes_dir.clone()];
+                classpath.extend(jars.iter().cloned());
+                let classpath = join_paths(&classpath)?;
+
+                let mut javac = self.ndk.javac()?;
+                javac
+                    .arg("-source")
+                    .arg("1.8")
+                    .arg("-target")
+                    .arg("1.8")
+                    // Don't warn about the obsolete source and target versions
+                    .arg("-Xlint:-options")
+                    .arg("-encoding")
+                    .arg("UTF-8")
+                    .arg("-bootclasspath")
+                    .arg(&android_jar)
+                    .arg("-classpath")
+                    .arg(classpath)
+                    .arg("-d")
+                    .arg(&classes_dir)
+                    .args(&java);
+                if !javac.status()?.success() {
+                    return Err(NdkError::CmdFailed(Box::new(javac)));
+                }
+            }
+
+            // Pass all classes to `d8` in a single JAR, to not exceed command line
+            // length limits
+            let classes_jar = self.build_dir.join("classes.jar");
+            write_classes_jar(&classes_dir, &classes_jar)?;
+            program.insert(0, classes_jar);
+        }
+
+        let mut d8 = self.build_tool(bat!("d8"))?;
+        d8.arg("--output")
+            .arg(&self.build_dir)
+            .arg("--lib")
+            .arg(&android_jar)
+            .arg("--min-api")
+            .arg(self.min_sdk_version().to_string());
+        if self.manifest.application.debuggable == Some(true) {
+            d8.arg("--debug");
+        } else {
+            d8.arg("--release");
+        }
+        d8.args(program);
+        if !d8.status()?.success() {
+            return Err(NdkError::CmdFailed(Box::new(d8)));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> UnalignedApk<'a> {
+    /// Compiles the code of the APK to dex files, unless its inputs didn't change
+    /// since the previous build, and adds them to the pending files
+    pub(crate) fn add_code(&mut self) -> Result<(), NdkError> {
+        let config = self.config;
+        let code = config.code_fingerprint()?;
+        let mut dex_files = list_dex_files(&config.build_dir)?;
+        if code != self.previous.code || dex_files.is_empty() {
+            config.compile_code()?;
+            dex_files = list_dex_files(&config.build_dir)?;
+        }
+        self.fingerprint.code = code;
+
+        for dex in dex_files {
+            let name = dex.file_name().unwrap().to_str().unwrap().to_string();
+            let mut hasher = InputHasher::default();
+            hasher.file(&dex)?;
+            self.fingerprint.libs.insert(name.clone(), hasher.finish());
+            self.pending_libs.insert(name);
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` for the `classes.dex`, `classes2.dex`, ... files written by `d8`
+pub(crate) fn is_dex_file(name: &str) -> bool {
+    name.strip_prefix("classes")
+        .and_then(|name| name.strip_suffix(".dex"))
+        .is_some_and(|index| index.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn list_dex_files(dir: &Path) -> Result<Vec<PathBuf>, NdkError> {
+    let mut files = Vec::new();
+    if !dir.exists() {
+        return Ok(files);
+    }
+    for entry in fs::read_dir(dir).map_err(|e| NdkError::IoPathError(dir.to_owned(), e))? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .and_then(OsStr::to_str)
+            .is_some_and(is_dex_file)
+        {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn join_paths(paths: &[PathBuf]) -> Result<OsString, NdkError> {
+    std::env::join_paths(paths)
+        .map_err(|e| NdkError::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)))
+}
+
+/// Packs all `.class` files in `classes_dir` into a JAR
+fn write_classes_jar(classes_dir: &Path, jar: &Path) -> Result<(), NdkError> {
+    let mut classes = Vec::new();
+    list_files_recursively(classes_dir, &mut classes)?;
+    classes.sort();
+
+    let file = File::create(jar).map_err(|e| NdkError::IoPathError(jar.to_owned(), e))?;
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+    for class in classes {
+        if class.extension() != Some(OsStr::new("class")) {
+            continue;
+        }
+        let name = class
+            .strip_prefix(classes_dir)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .replace('\\', "/");
+        let data = fs::read(&class).map_err(|e| NdkError::IoPathError(class.clone(), e))?;
+        zip.add_entry(&name, &data, Compression::Stored, DosDateTime::EPOCH, 1)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dex_file_names() {
+        assert!(is_dex_file("classes.dex"));
+        assert!(is_dex_file("classes12.dex"));
+        assert!(!is_dex_file("classes.jar"));
+        assert!(!is_dex_file("classesX.dex"));
+        assert!(!is_dex_file("lib/x86/classes.dex"));
+    }
+}
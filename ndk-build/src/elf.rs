@@ -0,0 +1,701 @@
+//! Minimal ELF reader for inspecting native libraries in-process.
+//!
+//! Only little-endian ELF files are supported, which covers every Android ABI. The
+//! dynamic section is read through the section headers, falling back to the program
+//! headers for libraries without section headers. Dynamic symbols are only read from the
+//! section headers. Debug files that only keep the debug sections have no dynamic
+//! section, but do keep their build-id.
+
+use crate::error::NdkError;
+use crate::target::Target;
+use std::convert::{TryFrom, TryInto};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"\x7fELF";
+const CLASS_32: u8 = 1;
+const CLASS_64: u8 = 2;
+const DATA_LITTLE_ENDIAN: u8 = 1;
+
+pub const EM_386: u16 = 3;
+pub const EM_ARM: u16 = 40;
+pub const EM_X86_64: u16 = 62;
+pub const EM_AARCH64: u16 = 183;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_NOTE: u32 = 4;
+
+const SHT_DYNAMIC: u32 = 6;
+const SHT_NOTE: u32 = 7;
+const SHT_DYNSYM: u32 = 11;
+
+const SHN_UNDEF: u16 = 0;
+const STB_GLOBAL: u8 = 1;
+const STB_WEAK: u8 = 2;
+const STV_DEFAULT: u8 = 0;
+const STV_PROTECTED: u8 = 3;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_STRSZ: u64 = 10;
+const DT_SONAME: u64 = 14;
+const DT_RPATH: u64 = 15;
+const DT_RUNPATH: u64 = 29;
+
+const NT_GNU_BUILD_ID: u32 = 3;
+
+/// A `PT_LOAD` segment
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LoadSegment {
+    pub offset: u64,
+    pub vaddr: u64,
+    pub file_size: u64,
+    pub mem_size: u64,
+    pub align: u64,
+}
+
+/// The properties of an ELF file that matter for packaging it into an APK
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Elf {
+    /// Whether this is a 64-bit (`ELFCLASS64`) file
+    pub is_64: bool,
+    /// `e_machine`, one of the `EM_*` constants for Android ABIs
+    pub machine: u16,
+    /// `DT_NEEDED` entries, in order
+    pub needed: Vec<String>,
+    /// `DT_SONAME` entry
+    pub soname: Option<String>,
+    /// Paths of the `DT_RUNPATH` entry, or else of the deprecated `DT_RPATH` entry
+    pub runpath: Vec<String>,
+    pub load_segments: Vec<LoadSegment>,
+    /// Contents of the `NT_GNU_BUILD_ID` note
+    pub build_id: Option<Vec<u8>>,
+    /// Global dynamic symbols that must be provided by another library. Weak
+    /// references are left out, as they resolve to null when they are missing.
+    pub undefined_symbols: Vec<String>,
+    /// Dynamic symbols that are exported to other libraries
+    pub defined_symbols: Vec<String>,
+}
+
+impl Elf {
+    /// Reads and parses the ELF file at `path`
+    pub fn read(path: &Path) -> Result<Self, NdkError> {
+        let data = std::fs::read(path)?;
+        Self::parse(&data).map_err(|e| match e {
+            NdkError::InvalidElf(message) => {
+                NdkError::InvalidElf(format!("{}: {}", path.display(), message))
+            }
+            e => e,
+        })
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, NdkError> {
+        let reader = Reader::new(data)?;
+        let machine = reader.u16(18)?;
+        let program_headers = reader.program_headers()?;
+        let sections = reader.sections()?;
+
+        let load_segments = program_headers
+            .iter()
+            .filter(|header| header.ty == PT_LOAD)
+            .map(|header| header.segment)
+            .collect::<Vec<_>>();
+
+        let dynamic = match sections.iter().find(|section| section.ty == SHT_DYNAMIC) {
+            Some(section) => {
+                let strtab = sections
+                    .get(section.link as usize)
+                    .ok_or_else(|| invalid("dynamic section links to a missing string table"))?;
+                Some((
+                    (section.offset, section.size),
+                    Some((strtab.offset, strtab.size)),
+                ))
+            }
+            None if sections.is_empty() => program_headers
+                .iter()
+                .find(|header| header.ty == PT_DYNAMIC)
+                .map(|header| ((header.segment.offset, header.segment.file_size), None)),
+            None => None,
+        };
+
+        let mut elf = Self {
+            is_64: reader.is_64,
+            machine,
+            load_segments,
+            ..Default::default()
+        };
+        if let Some((dynamic, strtab)) = dynamic {
+            reader.read_dynamic(&mut elf, dynamic, strtab)?;
+        }
+
+        if let Some(dynsym) = sections.iter().find(|section| section.ty == SHT_DYNSYM) {
+            let strtab = sections
+                .get(dynsym.link as usize)
+                .ok_or_else(|| invalid("symbol table links to a missing string table"))?;
+            reader.read_symbols(&mut elf, dynsym, strtab)?;
+        }
+
+        let notes = if sections.is_empty() {
+            program_headers
+                .iter()
+                .filter(|header| header.ty == PT_NOTE)
+                .map(|header| (header.segment.offset, header.segment.file_size))
+                .collect::<Vec<_>>()
+        } else {
+            sections
+                .iter()
+                .filter(|section| section.ty == SHT_NOTE)
+                .map(|section| (section.offset, section.size))
+                .collect()
+        };
+        for (offset, size) in notes {
+            if let Some(build_id) = reader.find_build_id(offset, size)? {
+                elf.build_id = Some(build_id);
+                break;
+            }
+        }
+        Ok(elf)
+    }
+
+    /// The Android target matching [`Elf::machine`] and the ELF class
+    pub fn target(&self) -> Option<Target> {
+        match (self.machine, self.is_64) {
+            (EM_ARM, false) => Some(Target::ArmV7a),
+            (EM_AARCH64, true) => Some(Target::Arm64V8a),
+            (EM_386, false) => Some(Target::X86),
+            (EM_X86_64, true) => Some(Target::X86_64),
+            _ => None,
+        }
+    }
+
+    /// The smallest alignment of the `PT_LOAD` segments, which is the largest page size
+    /// that the library can be loaded with
+    pub fn page_alignment(&self) -> Option<u64> {
+        self.load_segments.iter().map(|segment| segment.align).min()
+    }
+
+    /// [`Elf::build_id`] as a lowercase hex string
+    pub fn build_id_hex(&self) -> Option<String> {
+        self.build_id
+            .as_ref()
+            .map(|id| id.iter().map(|byte| format!("{byte:02x}")).collect())
+    }
+}
+
+fn invalid(message: &str) -> NdkError {
+    NdkError::InvalidElf(message.to_string())
+}
+
+/// Adds `delta` to the offset or address `base` read from the file, which may overflow in
+/// malformed files
+fn at(base: u64, delta: u64) -> Result<u64, NdkError> {
+    base.checked_add(delta)
+        .ok_or_else(|| invalid("offset out of range"))
+}
+
+struct ProgramHeader {
+    ty: u32,
+    segment: LoadSegment,
+}
+
+struct Section {
+    ty: u32,
+    offset: u64,
+    size: u64,
+    link: u32,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    is_64: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Result<Self, NdkError> {
+        if data.get(..4) != Some(MAGIC) {
+            return Err(invalid("not an ELF file"));
+        }
+        let is_64 = match data.get(4) {
+            Some(&CLASS_32) => false,
+            Some(&CLASS_64) => true,
+            _ => return Err(invalid("unknown ELF class")),
+        };
+        if data.get(5) != Some(&DATA_LITTLE_ENDIAN) {
+            return Err(invalid("big-endian ELF files are not supported"));
+        }
+        Ok(Self { data, is_64 })
+    }
+
+    fn slice(&self, offset: u64, len: u64) -> Result<&'a [u8], NdkError> {
+        usize::try_from(offset)
+            .ok()
+            .zip(usize::try_from(len).ok())
+            .and_then(|(offset, len)| self.data.get(offset..offset.checked_add(len)?))
+            .ok_or_else(|| invalid("unexpected end of file"))
+    }
+
+    fn u16(&self, offset: u64) -> Result<u16, NdkError> {
+        Ok(u16::from_le_bytes(
+            self.slice(offset, 2)?.try_into().unwrap(),
+        ))
+    }
+
+    fn u32(&self, offset: u64) -> Result<u32, NdkError> {
+        Ok(u32::from_le_bytes(
+            self.slice(offset, 4)?.try_into().unwrap(),
+        ))
+    }
+
+    fn u64(&self, offset: u64) -> Result<u64, NdkError> {
+        Ok(u64::from_le_bytes(
+            self.slice(offset, 8)?.try_into().unwrap(),
+        ))
+    }
+
+    /// Reads an address or offset, which is 4 bytes in 32-bit files
+    fn word(&self, offset: u64) -> Result<u64, NdkError> {
+        if self.is_64 {
+            self.u64(offset)
+        } else {
+            self.u32(offset).map(u64::from)
+        }
+    }
+
+    fn program_headers(&self) -> Result<Vec<ProgramHeader>, NdkError> {
+        let (offset, entry_size, count) = if self.is_64 {
+            (self.u64(32)?, self.u16(54)?, self.u16(56)?)
+        } else {
+            (u64::from(self.u32(28)?), self.u16(42)?, self.u16(44)?)
+        };
+        (0..u64::from(count))
+            .map(|i| {
+                let header = at(offset, i * u64::from(entry_size))?;
+                let field = |delta| self.word(at(header, delta)?);
+                let ty = self.u32(header)?;
+                let segment = if self.is_64 {
+                    LoadSegment {
+                        offset: field(8)?,
+                        vaddr: field(16)?,
+                        file_size: field(32)?,
+                        mem_size: field(40)?,
+                        align: field(48)?,
+                    }
+                } else {
+                    LoadSegment {
+                        offset: field(4)?,
+                        vaddr: field(8)?,
+                        file_size: field(16)?,
+                        mem_size: field(20)?,
+                        align: field(28)?,
+                    }
+                };
+                Ok(ProgramHeader { ty, segment })
+            })
+            .collect()
+    }
+
+    fn sections(&self) -> Result<Vec<Section>, NdkError> {
+        let (offset, entry_size, count) = if self.is_64 {
+            (self.u64(40)?, self.u16(58)?, self.u16(60)?)
+        } else {
+            (u64::from(self.u32(32)?), self.u16(46)?, self.u16(48)?)
+        };
+        if offset == 0 {
+            return Ok(Vec::new());
+        }
+        (0..u64::from(count))
+            .map(|i| {
+                let header = at(offset, i * u64::from(entry_size))?;
+                let (offset, size, link) = if self.is_64 {
+                    (24, 32, 40)
+                } else {
+                    (16, 20, 24)
+                };
+                Ok(Section {
+                    ty: self.u32(at(header, 4)?)?,
+                    offset: self.word(at(header, offset)?)?,
+                    size: self.word(at(header, size)?)?,
+                    link: self.u32(at(header, link)?)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Reads the `DT_*` entries of the dynamic section at `(offset, size)`. Without a
+    /// string table section, the string table is located through `DT_STRTAB`.
+    fn read_dynamic(
+        &self,
+        elf: &mut Elf,
+        (offset, size): (u64, u64),
+        strtab: Option<(u64, u64)>,
+    ) -> Result<(), NdkError> {
+        let entry_size = if self.is_64 { 16 } else { 8 };
+        let word_size = entry_size / 2;
+        let mut entries = Vec::new();
+        let (mut strtab_addr, mut strtab_size) = (None, None);
+        for i in 0..size / entry_size {
+            let entry = at(offset, i * entry_size)?;
+            let (tag, value) = (self.word(entry)?, self.word(at(entry, word_size)?)?);
+            match tag {
+                DT_NULL => break,
+                DT_STRTAB => strtab_addr = Some(value),
+                DT_STRSZ => strtab_size = Some(value),
+                DT_NEEDED | DT_SONAME | DT_RPATH | DT_RUNPATH => entries.push((tag, value)),
+                _ => {}
+            }
+        }
+
+        let strtab = match strtab {
+            Some(strtab) => strtab,
+            None => {
+                let addr = strtab_addr.ok_or_else(|| invalid("no DT_STRTAB entry"))?;
+                let mut segments = elf.load_segments.iter();
+                let (segment, end) = loop {
+                    let segment = segments
+                        .next()
+                        .ok_or_else(|| invalid("DT_STRTAB is outside of the loaded segments"))?;
+                    let end = at(segment.vaddr, segment.file_size)?;
+                    if segment.vaddr <= addr && addr < end {
+                        break (segment, end);
+                    }
+                };
+                let offset = at(addr - segment.vaddr, segment.offset)?;
+                let size = strtab_size.unwrap_or(end - addr);
+                (offset, size)
+            }
+        };
+        let strtab = self.slice(strtab.0, strtab.1)?;
+        let string = |index| string(strtab, index);
+
+        let mut rpath = Vec::new();
+        for (tag, value) in entries {
+            match tag {
+                DT_NEEDED => elf.needed.push(string(value)?),
+                DT_SONAME => elf.soname = Some(string(value)?),
+                DT_RUNPATH => elf.runpath = split_paths(&string(value)?),
+                _ => rpath = split_paths(&string(value)?),
+            }
+        }
+        if elf.runpath.is_empty() {
+            elf.runpath = rpath;
+        }
+        Ok(())
+    }
+
+    fn read_symbols(
+        &self,
+        elf: &mut Elf,
+        symtab: &Section,
+        strtab: &Section,
+    ) -> Result<(), NdkError> {
+        let strtab = self.slice(strtab.offset, strtab.size)?;
+        let entry_size = if self.is_64 { 24 } else { 16 };
+        // The first entry is the reserved undefined symbol
+        for i in 1..symtab.size / entry_size {
+            let symbol = at(symtab.offset, i * entry_size)?;
+            let (info, other, section) = if self.is_64 { (4, 5, 6) } else { (12, 13, 14) };
+            let info = self.slice(at(symbol, info)?, 1)?[0];
+            let visibility = self.slice(at(symbol, other)?, 1)?[0] & 0x3;
+            let section = self.u16(at(symbol, section)?)?;
+            let binding = info >> 4;
+            let name = string(strtab, u64::from(self.u32(symbol)?))?;
+            if name.is_empty() {
+                continue;
+            }
+            if section == SHN_UNDEF {
+                if binding == STB_GLOBAL {
+                    elf.undefined_symbols.push(name);
+                }
+            } else if (binding == STB_GLOBAL || binding == STB_WEAK)
+                && (visibility == STV_DEFAULT || visibility == STV_PROTECTED)
+            {
+                elf.defined_symbols.push(name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks for an `NT_GNU_BUILD_ID` note in the notes at `(offset, size)`
+    fn find_build_id(&self, offset: u64, size: u64) -> Result<Option<Vec<u8>>, NdkError> {
+        // Sizes are 32-bit values, so aligning them doesn't overflow
+        let align4 = |n: u64| (n + 3) & !3;
+        let end = at(offset, size)?;
+        let mut note = offset;
+        while at(note, 12)? <= end {
+            let name_size = u64::from(self.u32(note)?);
+            let desc_size = u64::from(self.u32(at(note, 4)?)?);
+            let ty = self.u32(at(note, 8)?)?;
+            let name = at(note, 12)?;
+            let desc = at(name, align4(name_size))?;
+            if ty == NT_GNU_BUILD_ID && self.slice(name, name_size)? == b"GNU\0" {
+                return Ok(Some(self.slice(desc, desc_size)?.to_vec()));
+            }
+            note = at(desc, align4(desc_size))?;
+        }
+        Ok(None)
+    }
+}
+
+/// Reads the NUL-terminated string at `index` in `strtab`
+fn string(strtab: &[u8], index: u64) -> Result<String, NdkError> {
+    let bytes = usize::try_from(index)
+        .ok()
+        .and_then(|index| strtab.get(index..))
+        .ok_or_else(|| invalid("string table index out of bounds"))?;
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or_else(|| invalid("unterminated string"))?;
+    Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
+
+fn split_paths(paths: &str) -> Vec<String> {
+    paths
+        .split(':')
+        .filter(|path| !path.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Assembles a 64-bit aarch64 library, see [`library_for()`]
+    pub(crate) fn library(
+        strings: &[&str],
+        dynamic: &[(u64, usize)],
+        symbols: &[(usize, u8, u16)],
+        build_id: &[u8],
+    ) -> Vec<u8> {
+        library_for(true, EM_AARCH64, strings, dynamic, symbols, build_id)
+    }
+
+    /// Assembles a 32-bit or 64-bit library with a `PT_LOAD` segment covering the whole
+    /// file, referring to `strings` by index in the dynamic entries and symbols
+    fn library_for(
+        is_64: bool,
+        machine: u16,
+        strings: &[&str],
+        dynamic: &[(u64, usize)],
+        symbols: &[(usize, u8, u16)],
+        build_id: &[u8],
+    ) -> Vec<u8> {
+        let push = |data: &mut Vec<u8>, values: &[u64], size: usize| {
+            for value in values {
+                data.extend_from_slice(&value.to_le_bytes()[..size]);
+            }
+        };
+        // Sizes of addresses and offsets, the ELF header, and of program headers, dynamic
+        // entries, symbols and section headers
+        let (word, ehdr, phdr, dyn_entry, sym, shdr) = if is_64 {
+            (8, 64, 56, 16, 24, 64)
+        } else {
+            (4, 52, 32, 8, 16, 40)
+        };
+        let phdrs = ehdr;
+        let strtab = phdrs + 3 * phdr;
+        let mut table = vec![0u8];
+        let mut indices = Vec::new();
+        for string in strings {
+            indices.push(table.len() as u64);
+            table.extend_from_slice(string.as_bytes());
+            table.push(0);
+        }
+        let dynamic_offset = (strtab + table.len() + 7) & !7;
+        let mut entries = dynamic
+            .iter()
+            .map(|&(tag, string)| (tag, indices[string]))
+            .collect::<Vec<_>>();
+        entries.extend_from_slice(&[
+            (DT_STRTAB, strtab as u64),
+            (DT_STRSZ, table.len() as u64),
+            (DT_NULL, 0),
+        ]);
+        let dynamic_size = entries.len() * dyn_entry;
+        let note_offset = dynamic_offset + dynamic_size;
+        let note_size = 16 + build_id.len();
+        let dynsym_offset = (note_offset + note_size + 7) & !7;
+        let dynsym_size = (symbols.len() + 1) * sym;
+        let shdrs = dynsym_offset + dynsym_size;
+        let file_size = shdrs + 5 * shdr;
+
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        let class = if is_64 { CLASS_64 } else { CLASS_32 };
+        data.extend_from_slice(&[class, DATA_LITTLE_ENDIAN, 1]);
+        data.resize(16, 0);
+        push(&mut data, &[3, u64::from(machine)], 2);
+        push(&mut data, &[1], 4);
+        push(&mut data, &[0, phdrs as u64, shdrs as u64], word);
+        push(&mut data, &[0], 4);
+        push(
+            &mut data,
+            &[ehdr as u64, phdr as u64, 3, shdr as u64, 5, 0],
+            2,
+        );
+        let segments = [
+            (PT_LOAD, 0, file_size, 0x4000),
+            (PT_DYNAMIC, dynamic_offset, dynamic_size, 8),
+            (PT_NOTE, note_offset, note_size, 4),
+        ];
+        for &(ty, offset, size, align) in segments.iter() {
+            let (offset, size) = (offset as u64, size as u64);
+            if is_64 {
+                push(&mut data, &[u64::from(ty), 4], 4);
+                push(&mut data, &[offset, offset, offset, size, size, align], 8);
+            } else {
+                push(
+                    &mut data,
+                    &[u64::from(ty), offset, offset, offset, size, size, 4, align],
+                    4,
+                );
+            }
+        }
+        data.extend_from_slice(&table);
+        data.resize(dynamic_offset, 0);
+        for (tag, value) in entries {
+            push(&mut data, &[tag, value], word);
+        }
+        push(
+            &mut data,
+            &[4, build_id.len() as u64, NT_GNU_BUILD_ID.into()],
+            4,
+        );
+        data.extend_from_slice(b"GNU\0");
+        data.extend_from_slice(build_id);
+        data.resize(dynsym_offset + sym, 0);
+        for &(name, info, section) in symbols {
+            push(&mut data, &[indices[name]], 4);
+            if !is_64 {
+                push(&mut data, &[0, 0], 4);
+            }
+            data.extend_from_slice(&[info, STV_DEFAULT]);
+            push(&mut data, &[section.into()], 2);
+            if is_64 {
+                push(&mut data, &[0, 0], 8);
+            }
+        }
+        let sections = [
+            (0, 0, 0, 0),
+            (3, strtab, table.len(), 0),
+            (SHT_DYNAMIC, dynamic_offset, dynamic_size, 1),
+            (SHT_NOTE, note_offset, note_size, 0),
+            (SHT_DYNSYM, dynsym_offset, dynsym_size, 1),
+        ];
+        for &(ty, offset, size, link) in sections.iter() {
+            push(&mut data, &[0, u64::from(ty)], 4);
+            push(
+                &mut data,
+                &[0, offset as u64, offset as u64, size as u64],
+                word,
+            );
+            push(&mut data, &[link, 0], 4);
+            push(&mut data, &[0, 0], word);
+        }
+        assert_eq!(data.len(), file_size);
+        data
+    }
+
+    #[test]
+    fn parse_library() {
+        let global = STB_GLOBAL << 4;
+        let weak = STB_WEAK << 4;
+        let mut data = library(
+            &[
+                "libc.so",
+                "libfoo.so",
+                "libbar.so",
+                "$ORIGIN:/opt/lib",
+                "malloc",
+                "__cxa_finalize",
+                "bar_init",
+            ],
+            &[
+                (DT_NEEDED, 0),
+                (DT_NEEDED, 1),
+                (DT_SONAME, 2),
+                (DT_RUNPATH, 3),
+            ],
+            &[(4, global, SHN_UNDEF), (5, weak, SHN_UNDEF), (6, global, 9)],
+            &[0xde, 0xad, 0xbe, 0xef],
+        );
+        let elf = Elf::parse(&data).unwrap();
+        assert_eq!(elf.target(), Some(Target::Arm64V8a));
+        assert_eq!(elf.needed, ["libc.so", "libfoo.so"]);
+        assert_eq!(elf.soname.as_deref(), Some("libbar.so"));
+        assert_eq!(elf.runpath, ["$ORIGIN", "/opt/lib"]);
+        assert_eq!(elf.page_alignment(), Some(0x4000));
+        assert_eq!(elf.build_id_hex().as_deref(), Some("deadbeef"));
+        assert_eq!(elf.undefined_symbols, ["malloc"]);
+        assert_eq!(elf.defined_symbols, ["bar_init"]);
+
+        // Without section headers, the program headers are used instead
+        data[40..48].copy_from_slice(&0u64.to_le_bytes());
+        let stripped = Elf::parse(&data).unwrap();
+        assert_eq!(
+            stripped,
+            Elf {
+                undefined_symbols: vec![],
+                defined_symbols: vec![],
+                ..elf
+            }
+        );
+
+        assert!(matches!(
+            Elf::parse(&data[..100]),
+            Err(NdkError::InvalidElf(_))
+        ));
+        assert!(matches!(
+            Elf::parse(b"!<arch>\n"),
+            Err(NdkError::InvalidElf(_))
+        ));
+    }
+
+    #[test]
+    fn parse_32_bit_libraries() {
+        let global = STB_GLOBAL << 4;
+        for &(machine, target) in [(EM_ARM, Target::ArmV7a), (EM_386, Target::X86)].iter() {
+            let data = library_for(
+                false,
+                machine,
+                &["libc.so", "libfoo.so", "malloc", "foo_init"],
+                &[(DT_NEEDED, 0), (DT_SONAME, 1)],
+                &[(2, global, SHN_UNDEF), (3, global, 9)],
+                &[0xca, 0xfe],
+            );
+            let elf = Elf::parse(&data).unwrap();
+            assert!(!elf.is_64);
+            assert_eq!(elf.target(), Some(target));
+            assert_eq!(elf.needed, ["libc.so"]);
+            assert_eq!(elf.soname.as_deref(), Some("libfoo.so"));
+            assert_eq!(elf.page_alignment(), Some(0x4000));
+            assert_eq!(elf.build_id_hex().as_deref(), Some("cafe"));
+            assert_eq!(elf.undefined_symbols, ["malloc"]);
+            assert_eq!(elf.defined_symbols, ["foo_init"]);
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_offsets() {
+        let data = library(&["libc.so"], &[(DT_NEEDED, 0)], &[], &[0xde, 0xad]);
+
+        // A note section that ends beyond the address space
+        let mut notes = data.clone();
+        let shdrs = u64::from_le_bytes(notes[40..48].try_into().unwrap()) as usize;
+        let note_size = shdrs + 3 * 64 + 32;
+        notes[note_size..note_size + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(Elf::parse(&notes), Err(NdkError::InvalidElf(_))));
+
+        // Without section headers, a `DT_STRTAB` lookup in a segment that wraps around
+        let mut segments = data;
+        segments[40..48].copy_from_slice(&0u64.to_le_bytes());
+        let load_vaddr = 64 + 16;
+        segments[load_vaddr..load_vaddr + 8].copy_from_slice(&(u64::MAX - 8).to_le_bytes());
+        assert!(matches!(
+            Elf::parse(&segments),
+            Err(NdkError::InvalidElf(_))
+        ));
+    }
+}
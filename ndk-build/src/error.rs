@@ -54,4 +54,60 @@ pub enum NdkError {
     PackageNotInOutput { package: String, output: String },
     #[error("Could not find `uid:` in output `{0}`")]
     UidNotInOutput(String),
+    #[error("Invalid zip archive: {0}")]
+    InvalidZip(String),
+    #[error("Failed to load signing key from `{0:?}`: {1}")]
+    InvalidKey(PathBuf, String),
+    #[error("Signing key in `{0:?}` is not an RSA key, which is the only supported algorithm")]
+    UnsupportedKeyAlgorithm(PathBuf),
+    #[error("Invalid certificate: {0}")]
+    InvalidCertificate(String),
+    #[error("Certificate does not match the private key in `{0:?}`")]
+    CertificateMismatch(PathBuf),
+    #[error("Failed to sign APK: {0}")]
+    Signing(String),
+    #[error("The `apksigner` backend does not support PEM keys, use a keystore instead")]
+    ApksignerPemKey,
+    #[error("None of the split APKs supports the device ABIs `{0}`")]
+    NoSplitForDeviceAbis(String),
+    #[error("`SOURCE_DATE_EPOCH` must be a Unix timestamp, got `{0}`")]
+    InvalidSourceDateEpoch(String),
+    #[error("No Java or Kotlin sources found in `{0:?}`")]
+    NoJavaSources(PathBuf),
+    #[error("Failed to merge manifests: {0}")]
+    ManifestMerge(String),
+    #[error("The manifest does not declare an activity to start")]
+    NoLauncherActivity,
+    #[error("Maven artifact `{0}` not found")]
+    MavenArtifactNotFound(String),
+    #[error("Checksum of `{url}` is `{actual}` instead of the published `{expected}`")]
+    MavenChecksumMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("No `.sha256` or `.sha1` checksum is published for `{0}`")]
+    MavenChecksumNotFound(String),
+    #[error("Unsupported feature in the POM of `{0}`: {1}")]
+    UnsupportedPom(String, String),
+    #[error("Shared library `{library}` not found, needed by {}", .chain.join(" -> "))]
+    MissingSharedLibrary {
+        library: String,
+        /// The library that was added, followed by the dependencies that lead to the
+        /// library that needs `library`
+        chain: Vec<String>,
+    },
+    #[error("Symbols not available on API level {0}, raise `min_sdk_version` or reference them weakly:{1}")]
+    UnavailableSymbols(u32, String),
+    #[error("`{0:?}` has LOAD segments aligned to {1} bytes and fails to load on devices with 16 KiB pages. `page_size_16k` only links the libraries that cargo builds with `-Wl,-z,max-page-size=16384`; relink this library, e.g. a prebuilt one, with that flag or replace it with a 16 KiB aligned build (NDK r28 and newer align by default)")]
+    UnalignedLoadSegments(PathBuf, u64),
+    #[error("Invalid ELF file: {0}")]
+    InvalidElf(String),
+    #[error("Build-id {} of `{library:?}` differs from build-id {} of its debug file `{debug_file:?}`", .library_build_id.as_deref().unwrap_or("<none>"), .debug_build_id.as_deref().unwrap_or("<none>"))]
+    BuildIdMismatch {
+        library: PathBuf,
+        library_build_id: Option<String>,
+        debug_file: PathBuf,
+        debug_build_id: Option<String>,
+    },
 }
@@ -0,0 +1,165 @@
+//! Fingerprints of the inputs of every packaging step, stored in the build directory
+//! so that the next build can skip steps whose inputs did not change.
+
+use crate::error::NdkError;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Hashes of the inputs of the packaging steps of a single APK
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Fingerprint {
+    /// Manifest, resources, assets and `aapt2 link` options
+    pub(crate) link: String,
+    /// Java and Kotlin sources, JARs and `d8` options
+    pub(crate) code: String,
+    /// Contents of every native library and how it is stripped, and of every dex
+    /// file, by zip entry name
+    pub(crate) libs: BTreeMap<String, String>,
+    /// Options that affect how libraries are added to the APK
+    pub(crate) package: String,
+    /// Signing key and signer options
+    pub(crate) signing: String,
+}
+
+impl Fingerprint {
+    /// Loads the fingerprint written by a previous build, falling back to an empty
+    /// fingerprint that matches no inputs when it is missing or unreadable
+    pub(crate) fn load(path: &Path) -> Self {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return Self::default(),
+        };
+        let mut fingerprint = Self::default();
+        for line in contents.lines() {
+            let mut parts = line.splitn(3, ' ');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some("link"), Some(hash), None) => fingerprint.link = hash.to_string(),
+                (Some("code"), Some(hash), None) => fingerprint.code = hash.to_string(),
+                (Some("package"), Some(hash), None) => fingerprint.package = hash.to_string(),
+                (Some("signing"), Some(hash), None) => fingerprint.signing = hash.to_string(),
+                (Some("lib"), Some(hash), Some(name)) => {
+                    fingerprint.libs.insert(name.to_string(), hash.to_string());
+                }
+                _ => return Self::default(),
+            }
+        }
+        fingerprint
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<(), NdkError> {
+        let mut contents = format!(
+            "link {}\ncode {}\npackage {}\nsigning {}\n",
+            self.link, self.code, self.package, self.signing
+        );
+        for (name, hash) in &self.libs {
+            contents.push_str(&format!("lib {hash} {name}\n"));
+        }
+        fs::write(path, contents).map_err(|e| NdkError::IoPathError(path.to_owned(), e))
+    }
+
+    /// Returns `true` when the library at zip entry `name` has the same contents and
+    /// is added to the APK in the same way as in the `previous` build
+    pub(crate) fn lib_unchanged(&self, previous: &Self, name: &str) -> bool {
+        self.package == previous.package
+            && self.libs.contains_key(name)
+            && self.libs.get(name) == previous.libs.get(name)
+    }
+}
+
+/// Hashes packaging inputs into a fingerprint
+#[derive(Default)]
+pub(crate) struct InputHasher(Sha256);
+
+impl InputHasher {
+    pub(crate) fn bytes(&mut self, data: &[u8]) {
+        // Length-prefix every input, so that consecutive inputs can't be confused
+        self.0.update((data.len() as u64).to_le_bytes());
+        self.0.update(data);
+    }
+
+    pub(crate) fn str(&mut self, s: &str) {
+        self.bytes(s.as_bytes());
+    }
+
+    pub(crate) fn path(&mut self, path: &Path) {
+        self.str(&path.to_string_lossy());
+    }
+
+    /// Hashes the contents of the file at `path`
+    pub(crate) fn file(&mut self, path: &Path) -> Result<(), NdkError> {
+        let data = fs::read(path).map_err(|e| NdkError::IoPathError(path.to_owned(), e))?;
+        self.bytes(&data);
+        Ok(())
+    }
+
+    /// Hashes the relative path, size and modification time of every file in `dir`,
+    /// recursively
+    pub(crate) fn dir(&mut self, dir: &Path) -> Result<(), NdkError> {
+        let mut files = Vec::new();
+        list_files_recursively(dir, &mut files)?;
+        files.sort();
+        for file in files {
+            let metadata = fs::metadata(&file)?;
+            let modified = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0);
+            self.path(file.strip_prefix(dir).unwrap());
+            self.bytes(&metadata.len().to_le_bytes());
+            self.bytes(&modified.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> String {
+        self.0
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+}
+
+pub(crate) fn list_files_recursively(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), NdkError> {
+    for entry in fs::read_dir(dir).map_err(|e| NdkError::IoPathError(dir.to_owned(), e))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files_recursively(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_roundtrip() {
+        let mut fingerprint = Fingerprint {
+            link: "aa".to_string(),
+            code: "ee".to_string(),
+            package: "bb".to_string(),
+            signing: "cc".to_string(),
+            ..Default::default()
+        };
+        fingerprint
+            .libs
+            .insert("lib/arm64-v8a/lib foo.so".to_string(), "dd".to_string());
+
+        let path =
+            std::env::temp_dir().join(format!("ndk-build-fingerprint-{}", std::process::id()));
+        fingerprint.save(&path).unwrap();
+        let loaded = Fingerprint::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, fingerprint);
+        assert!(loaded.lib_unchanged(&fingerprint, "lib/arm64-v8a/lib foo.so"));
+        assert!(!loaded.lib_unchanged(&Fingerprint::default(), "lib/arm64-v8a/lib foo.so"));
+    }
+}
@@ -28,11 +28,22 @@ macro_rules! cmd {
     };
 }
 
+pub mod aar;
 pub mod apk;
+pub mod bundle;
 pub mod cargo;
+pub mod debug_symbols;
+pub mod dex;
 pub mod dylibs;
+pub mod elf;
 pub mod error;
+mod fingerprint;
+pub mod keystore;
 pub mod manifest;
+pub mod manifest_merger;
+pub mod maven;
 pub mod ndk;
 pub mod readelf;
+pub mod sign;
 pub mod target;
+pub mod zip;
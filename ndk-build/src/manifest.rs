@@ -1,9 +1,17 @@
 use crate::error::NdkError;
-use serde::{Deserialize, Serialize, Serializer};
-use std::{fs::File, path::Path};
+use crate::manifest_merger::Element;
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{self, DeserializeOwned, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    cell::Cell,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
 
 /// Android [manifest element](https://developer.android.com/guide/topics/manifest/manifest-element), containing an [`Application`] element.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename = "manifest")]
 pub struct AndroidManifest {
     #[serde(rename(serialize = "xmlns:android"))]
@@ -34,6 +42,13 @@ pub struct AndroidManifest {
 
     #[serde(default)]
     pub application: Application,
+
+    /// `AndroidManifest.xml` files that are merged into the generated manifest by
+    /// [`AndroidManifest::write_to`], each taking priority over the ones before it.
+    /// See the [`manifest_merger`](crate::manifest_merger) module for the merge rules.
+    #[serde(rename(deserialize = "android_manifest_overlays"))]
+    #[serde(default, skip_serializing)]
+    pub overlays: Vec<PathBuf>,
 }
 
 impl Default for AndroidManifest {
@@ -49,21 +64,54 @@ impl Default for AndroidManifest {
             uses_permission: Default::default(),
             queries: Default::default(),
             application: Default::default(),
+            overlays: Default::default(),
         }
     }
 }
 
 impl AndroidManifest {
     pub fn write_to(&self, dir: &Path) -> Result<(), NdkError> {
-        let file = File::create(dir.join("AndroidManifest.xml"))?;
-        let w = std::io::BufWriter::new(file);
-        quick_xml::se::to_writer(w, &self)?;
-        Ok(())
+        self.write_merged_to(dir, &[])
+    }
+
+    /// Writes the manifest to `dir` after merging the manifests of `libraries` into
+    /// it, which have the lowest priority, followed by [`AndroidManifest::overlays`].
+    ///
+    /// `${applicationId}` placeholders in this manifest and the merged manifests are
+    /// replaced with [`AndroidManifest::package`].
+    pub fn write_merged_to(&self, dir: &Path, libraries: &[PathBuf]) -> Result<(), NdkError> {
+        let path = dir.join("AndroidManifest.xml");
+        let generated = quick_xml::se::to_string(&self)?.replace("${applicationId}", &self.package);
+        if libraries.is_empty() && self.overlays.is_empty() {
+            return fs::write(&path, generated).map_err(|e| NdkError::IoPathError(path, e));
+        }
+
+        let read = |path: &Path| -> Result<Element, NdkError> {
+            let xml = fs::read_to_string(path)
+                .map_err(|e| NdkError::IoPathError(path.to_owned(), e))?
+                .replace("${applicationId}", &self.package);
+            Element::parse(&xml, &path.display().to_string(), true).map_err(NdkError::ManifestMerge)
+        };
+        let mut merged = Element::parse(&generated, "the generated manifest", false)
+            .map_err(NdkError::ManifestMerge)?;
+        for library in libraries {
+            merged
+                .merge_library(read(library)?)
+                .map_err(NdkError::ManifestMerge)?;
+        }
+        for overlay in &self.overlays {
+            merged
+                .merge_overlay(read(overlay)?)
+                .map_err(NdkError::ManifestMerge)?;
+        }
+        fs::write(&path, merged.to_xml()).map_err(|e| NdkError::IoPathError(path, e))
     }
 }
 
-/// Android [application element](https://developer.android.com/guide/topics/manifest/application-element), containing an [`Activity`] element.
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+/// Android [application element](https://developer.android.com/guide/topics/manifest/application-element), containing [`Activity`] and
+/// [`ActivityAlias`] elements and any number of [`Service`], [`Receiver`] and [`Provider`] elements.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Application {
     #[serde(rename(serialize = "android:debuggable"))]
     pub debuggable: Option<bool>,
@@ -85,12 +133,148 @@ pub struct Application {
     #[serde(rename(serialize = "meta-data"))]
     #[serde(default)]
     pub meta_data: Vec<MetaData>,
+    /// Deserialized from either a single activity or an array of activities, defaults to a
+    /// single [`Activity::default()`].
+    #[serde(default = "default_activities")]
+    #[serde(deserialize_with = "deserialize_activities")]
+    #[cfg_attr(feature = "schemars", schemars(schema_with = "activities_schema"))]
+    pub activity: Vec<Activity>,
+    #[serde(rename(serialize = "activity-alias"))]
+    #[serde(default)]
+    pub activity_alias: Vec<ActivityAlias>,
+    #[serde(default)]
+    pub service: Vec<Service>,
     #[serde(default)]
-    pub activity: Activity,
+    pub receiver: Vec<Receiver>,
+    #[serde(default)]
+    pub provider: Vec<Provider>,
+}
+
+impl Default for Application {
+    fn default() -> Self {
+        Self {
+            debuggable: Default::default(),
+            theme: Default::default(),
+            has_code: Default::default(),
+            icon: Default::default(),
+            label: Default::default(),
+            extract_native_libs: Default::default(),
+            uses_cleartext_traffic: Default::default(),
+            meta_data: Default::default(),
+            activity: default_activities(),
+            activity_alias: Default::default(),
+            service: Default::default(),
+            receiver: Default::default(),
+            provider: Default::default(),
+        }
+    }
+}
+
+impl Application {
+    fn launcher_activity_index(&self) -> Option<usize> {
+        self.activity
+            .iter()
+            .position(|activity| activity.launcher)
+            .or_else(|| self.activity.iter().position(Activity::has_main_action))
+            .or_else(|| (!self.activity.is_empty()).then_some(0))
+    }
+
+    /// The activity that is started from the launcher: the first activity marked as
+    /// [`Activity::launcher`], or else the first one with a `MAIN` intent filter, or else the
+    /// first activity.
+    pub fn launcher_activity(&self) -> Option<&Activity> {
+        self.launcher_activity_index().map(|i| &self.activity[i])
+    }
+
+    /// Mutable access to the [`Application::launcher_activity()`]
+    pub fn launcher_activity_mut(&mut self) -> Option<&mut Activity> {
+        self.launcher_activity_index()
+            .map(move |i| &mut self.activity[i])
+    }
+}
+
+/// Schema of [`deserialize_activities()`]
+#[cfg(feature = "schemars")]
+fn activities_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+    use schemars::schema::{SchemaObject, SubschemaValidation};
+    SchemaObject {
+        subschemas: Some(Box::new(SubschemaValidation {
+            any_of: Some(vec![
+                gen.subschema_for::<Activity>(),
+                gen.subschema_for::<Vec<Activity>>(),
+            ]),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+fn deserialize_activities<'de, D>(deserializer: D) -> Result<Vec<Activity>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    // Not an untagged enum, which would buffer the input and lose the location of errors
+    struct OneOrMany;
+
+    impl<'de> Visitor<'de> for OneOrMany {
+        type Value = Vec<Activity>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("an activity table or an array of activity tables")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            Activity::deserialize(MapAccessDeserializer::new(map)).map(|activity| vec![activity])
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+            Vec::deserialize(SeqAccessDeserializer::new(seq))
+        }
+    }
+
+    // Requested as a struct, so that deserializers can tell which keys a single activity
+    // table accepts. TOML deserializers treat this like `deserialize_any()`.
+    deserializer.deserialize_struct("Activity", struct_fields::<Activity>(), OneOrMany)
+}
+
+/// The fields that the derived `Deserialize` implementation of `T` passes to
+/// [`Deserializer::deserialize_struct()`]
+fn struct_fields<T: DeserializeOwned>() -> &'static [&'static str] {
+    struct FieldsDeserializer<'a>(&'a Cell<&'static [&'static str]>);
+
+    impl<'de> Deserializer<'de> for FieldsDeserializer<'_> {
+        type Error = serde::de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            self.0.set(fields);
+            Err(de::Error::custom("only the fields are read"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+            byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map enum
+            identifier ignored_any
+        }
+    }
+
+    let fields = Cell::new(&[][..]);
+    let _ = T::deserialize(FieldsDeserializer(&fields));
+    fields.get()
 }
 
 /// Android [activity element](https://developer.android.com/guide/topics/manifest/activity-element).
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Activity {
     #[serde(rename(serialize = "android:configChanges"))]
     #[serde(default = "default_config_changes")]
@@ -114,10 +298,25 @@ pub struct Activity {
     #[serde(rename(serialize = "meta-data"))]
     #[serde(default)]
     pub meta_data: Vec<MetaData>,
-    /// If no `MAIN` action exists in any intent filter, a default `MAIN` filter is serialized by `cargo-apk`.
+    /// If no `MAIN` action exists in any intent filter of the [launcher activity](Application::launcher_activity()),
+    /// a default `MAIN` filter is serialized by `cargo-apk`.
     #[serde(rename(serialize = "intent-filter"))]
     #[serde(default)]
     pub intent_filter: Vec<IntentFilter>,
+
+    /// Marks the activity that `cargo-apk` adds the default `MAIN` filter and `android.app.lib_name`
+    /// to, and that is started by `cargo apk run`. Not serialized to the manifest.
+    #[serde(default, skip_serializing)]
+    pub launcher: bool,
+}
+
+impl Activity {
+    /// Whether any intent filter of the activity has the `MAIN` action
+    pub fn has_main_action(&self) -> bool {
+        self.intent_filter
+            .iter()
+            .any(|i| i.actions.iter().any(|a| a == "android.intent.action.MAIN"))
+    }
 }
 
 impl Default for Activity {
@@ -133,19 +332,150 @@ impl Default for Activity {
             always_retain_task_state: None,
             meta_data: Default::default(),
             intent_filter: Default::default(),
+            launcher: false,
         }
     }
 }
 
+/// Android [activity-alias element](https://developer.android.com/guide/topics/manifest/activity-alias-element),
+/// e.g. to provide alternate launcher icons for an [`Activity`].
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ActivityAlias {
+    #[serde(rename(serialize = "android:name"))]
+    pub name: String,
+    #[serde(rename(serialize = "android:targetActivity"))]
+    pub target_activity: String,
+    #[serde(rename(serialize = "android:label"))]
+    pub label: Option<String>,
+    #[serde(rename(serialize = "android:icon"))]
+    pub icon: Option<String>,
+    #[serde(rename(serialize = "android:enabled"))]
+    pub enabled: Option<bool>,
+    #[serde(rename(serialize = "android:exported"))]
+    pub exported: Option<bool>,
+    #[serde(rename(serialize = "android:permission"))]
+    pub permission: Option<String>,
+
+    #[serde(rename(serialize = "meta-data"))]
+    #[serde(default)]
+    pub meta_data: Vec<MetaData>,
+    #[serde(rename(serialize = "intent-filter"))]
+    #[serde(default)]
+    pub intent_filter: Vec<IntentFilter>,
+}
+
+/// Android [service element](https://developer.android.com/guide/topics/manifest/service-element).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Service {
+    #[serde(rename(serialize = "android:name"))]
+    pub name: String,
+    #[serde(rename(serialize = "android:label"))]
+    pub label: Option<String>,
+    #[serde(rename(serialize = "android:icon"))]
+    pub icon: Option<String>,
+    #[serde(rename(serialize = "android:enabled"))]
+    pub enabled: Option<bool>,
+    #[serde(rename(serialize = "android:exported"))]
+    pub exported: Option<bool>,
+    #[serde(rename(serialize = "android:permission"))]
+    pub permission: Option<String>,
+    #[serde(rename(serialize = "android:process"))]
+    pub process: Option<String>,
+    #[serde(rename(serialize = "android:isolatedProcess"))]
+    pub isolated_process: Option<bool>,
+    /// Required on Android >= 34 (U and up) for foreground services, e.g. `"dataSync|location"`.
+    #[serde(rename(serialize = "android:foregroundServiceType"))]
+    pub foreground_service_type: Option<String>,
+
+    #[serde(rename(serialize = "meta-data"))]
+    #[serde(default)]
+    pub meta_data: Vec<MetaData>,
+    #[serde(rename(serialize = "intent-filter"))]
+    #[serde(default)]
+    pub intent_filter: Vec<IntentFilter>,
+}
+
+/// Android [receiver element](https://developer.android.com/guide/topics/manifest/receiver-element).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Receiver {
+    #[serde(rename(serialize = "android:name"))]
+    pub name: String,
+    #[serde(rename(serialize = "android:label"))]
+    pub label: Option<String>,
+    #[serde(rename(serialize = "android:icon"))]
+    pub icon: Option<String>,
+    #[serde(rename(serialize = "android:enabled"))]
+    pub enabled: Option<bool>,
+    #[serde(rename(serialize = "android:exported"))]
+    pub exported: Option<bool>,
+    #[serde(rename(serialize = "android:permission"))]
+    pub permission: Option<String>,
+    #[serde(rename(serialize = "android:process"))]
+    pub process: Option<String>,
+    #[serde(rename(serialize = "android:directBootAware"))]
+    pub direct_boot_aware: Option<bool>,
+
+    #[serde(rename(serialize = "meta-data"))]
+    #[serde(default)]
+    pub meta_data: Vec<MetaData>,
+    #[serde(rename(serialize = "intent-filter"))]
+    #[serde(default)]
+    pub intent_filter: Vec<IntentFilter>,
+}
+
+/// Android [provider element](https://developer.android.com/guide/topics/manifest/provider-element).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Provider {
+    #[serde(rename(serialize = "android:name"))]
+    pub name: String,
+    /// One or more authorities separated by semicolons, e.g. `"com.example.app.fileprovider"`.
+    #[serde(rename(serialize = "android:authorities"))]
+    pub authorities: String,
+    #[serde(rename(serialize = "android:label"))]
+    pub label: Option<String>,
+    #[serde(rename(serialize = "android:enabled"))]
+    pub enabled: Option<bool>,
+    #[serde(rename(serialize = "android:exported"))]
+    pub exported: Option<bool>,
+    #[serde(rename(serialize = "android:grantUriPermissions"))]
+    pub grant_uri_permissions: Option<bool>,
+    #[serde(rename(serialize = "android:permission"))]
+    pub permission: Option<String>,
+    #[serde(rename(serialize = "android:readPermission"))]
+    pub read_permission: Option<String>,
+    #[serde(rename(serialize = "android:writePermission"))]
+    pub write_permission: Option<String>,
+    #[serde(rename(serialize = "android:process"))]
+    pub process: Option<String>,
+    #[serde(rename(serialize = "android:multiprocess"))]
+    pub multiprocess: Option<bool>,
+    #[serde(rename(serialize = "android:initOrder"))]
+    pub init_order: Option<i32>,
+
+    #[serde(rename(serialize = "meta-data"))]
+    #[serde(default)]
+    pub meta_data: Vec<MetaData>,
+    #[serde(rename(serialize = "intent-filter"))]
+    #[serde(default)]
+    pub intent_filter: Vec<IntentFilter>,
+}
+
 /// Android [intent filter element](https://developer.android.com/guide/topics/manifest/intent-filter-element).
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct IntentFilter {
-    /// Serialize strings wrapped in `<action android:name="..." />`
+    /// Names of the actions, e.g. `"android.intent.action.VIEW"`, each serialized as an
+    /// `<action android:name="..." />` element
     #[serde(serialize_with = "serialize_actions")]
     #[serde(rename(serialize = "action"))]
     #[serde(default)]
     pub actions: Vec<String>,
-    /// Serialize as vector of structs for proper xml formatting
+    /// Names of the categories, e.g. `"android.intent.category.BROWSABLE"`, each serialized
+    /// as a `<category android:name="..." />` element
     #[serde(serialize_with = "serialize_catergories")]
     #[serde(rename(serialize = "category"))]
     #[serde(default)]
@@ -197,6 +527,7 @@ where
 
 /// Android [intent filter data element](https://developer.android.com/guide/topics/manifest/data-element).
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct IntentFilterData {
     #[serde(rename(serialize = "android:scheme"))]
     pub scheme: Option<String>,
@@ -216,15 +547,22 @@ pub struct IntentFilterData {
 
 /// Android [meta-data element](https://developer.android.com/guide/topics/manifest/meta-data-element).
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct MetaData {
     #[serde(rename(serialize = "android:name"))]
     pub name: String,
     #[serde(rename(serialize = "android:value"))]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub value: String,
+    /// Reference to a resource instead of a [`MetaData::value`], e.g. the `@xml/file_paths`
+    /// of a `FileProvider`.
+    #[serde(rename(serialize = "android:resource"))]
+    pub resource: Option<String>,
 }
 
 /// Android [uses-feature element](https://developer.android.com/guide/topics/manifest/uses-feature-element).
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Feature {
     #[serde(rename(serialize = "android:name"))]
     pub name: Option<String>,
@@ -265,6 +603,7 @@ where
 
 /// Android [uses-permission element](https://developer.android.com/guide/topics/manifest/uses-permission-element).
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Permission {
     #[serde(rename(serialize = "android:name"))]
     pub name: String,
@@ -274,6 +613,7 @@ pub struct Permission {
 
 /// Android [package element](https://developer.android.com/guide/topics/manifest/queries-element#package).
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Package {
     #[serde(rename(serialize = "android:name"))]
     pub name: String,
@@ -281,18 +621,18 @@ pub struct Package {
 
 /// Android [provider element](https://developer.android.com/guide/topics/manifest/queries-element#provider).
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct QueryProvider {
     #[serde(rename(serialize = "android:authorities"))]
     pub authorities: String,
 
-    // The specs say only an `authorities` attribute is required for providers contained in a `queries` element
-    // however this is required for aapt support and should be made optional if/when cargo-apk migrates to aapt2
     #[serde(rename(serialize = "android:name"))]
-    pub name: String,
+    pub name: Option<String>,
 }
 
 /// Android [queries element](https://developer.android.com/guide/topics/manifest/queries-element).
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Queries {
     #[serde(default)]
     pub package: Vec<Package>,
@@ -304,6 +644,7 @@ pub struct Queries {
 
 /// Android [uses-sdk element](https://developer.android.com/guide/topics/manifest/uses-sdk-element).
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Sdk {
     #[serde(rename(serialize = "android:minSdkVersion"))]
     pub min_sdk_version: Option<u32>,
@@ -327,6 +668,10 @@ fn default_namespace() -> String {
     "http://schemas.android.com/apk/res/android".to_string()
 }
 
+fn default_activities() -> Vec<Activity> {
+    vec![Activity::default()]
+}
+
 fn default_activity_name() -> String {
     "android.app.NativeActivity".to_string()
 }
@@ -334,3 +679,105 @@ fn default_activity_name() -> String {
 fn default_config_changes() -> Option<String> {
     Some("orientation|keyboardHidden|screenSize".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_application_id() {
+        let dir = std::env::temp_dir().join(format!("ndk-build-manifest-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut manifest = AndroidManifest {
+            package: "com.example.app".to_string(),
+            ..Default::default()
+        };
+        manifest.application.meta_data.push(MetaData {
+            name: "authority".to_string(),
+            value: "${applicationId}.files".to_string(),
+            resource: None,
+        });
+        let written = dir.join("AndroidManifest.xml");
+
+        manifest.write_to(&dir).unwrap();
+        let xml = fs::read_to_string(&written).unwrap();
+        assert!(xml.contains(r#"android:value="com.example.app.files""#));
+        assert!(!xml.contains("${applicationId}"));
+
+        let overlay = dir.join("overlay.xml");
+        fs::write(
+            &overlay,
+            r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android"><application><provider android:name="androidx.core.content.FileProvider" android:authorities="${applicationId}.provider" /></application></manifest>"#,
+        )
+        .unwrap();
+        manifest.overlays.push(overlay);
+        manifest.write_to(&dir).unwrap();
+        let xml = fs::read_to_string(&written).unwrap();
+        assert!(xml.contains(r#"android:value="com.example.app.files""#));
+        assert!(xml.contains(r#"android:authorities="com.example.app.provider""#));
+        assert!(!xml.contains("${applicationId}"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn serializes_components() {
+        let meta_data = vec![
+            MetaData {
+                name: "android.support.FILE_PROVIDER_PATHS".to_string(),
+                value: String::new(),
+                resource: Some("@xml/file_paths".to_string()),
+            },
+            MetaData {
+                name: "version".to_string(),
+                value: "2".to_string(),
+                resource: None,
+            },
+        ];
+        let intent_filter = vec![IntentFilter {
+            actions: vec!["android.intent.action.BOOT_COMPLETED".to_string()],
+            ..Default::default()
+        }];
+        let mut manifest = AndroidManifest {
+            package: "com.example.app".to_string(),
+            ..Default::default()
+        };
+        manifest.application.service.push(Service {
+            name: ".SyncService".to_string(),
+            exported: Some(false),
+            foreground_service_type: Some("dataSync".to_string()),
+            intent_filter: intent_filter.clone(),
+            ..Default::default()
+        });
+        manifest.application.receiver.push(Receiver {
+            name: ".BootReceiver".to_string(),
+            direct_boot_aware: Some(true),
+            intent_filter,
+            ..Default::default()
+        });
+        manifest.application.provider.push(Provider {
+            name: "androidx.core.content.FileProvider".to_string(),
+            authorities: "${applicationId}.fileprovider".to_string(),
+            exported: Some(false),
+            grant_uri_permissions: Some(true),
+            meta_data,
+            ..Default::default()
+        });
+
+        let xml = quick_xml::se::to_string(&manifest).unwrap();
+        assert!(xml.contains(
+            r#"<service android:name=".SyncService" android:exported="false" android:foregroundServiceType="dataSync"><intent-filter><action android:name="android.intent.action.BOOT_COMPLETED"/></intent-filter></service>"#
+        ));
+        assert!(xml.contains(
+            r#"<receiver android:name=".BootReceiver" android:directBootAware="true"><intent-filter><action android:name="android.intent.action.BOOT_COMPLETED"/></intent-filter></receiver>"#
+        ));
+        assert!(xml.contains(
+            r#"<provider android:name="androidx.core.content.FileProvider" android:authorities="${applicationId}.fileprovider" android:exported="false" android:grantUriPermissions="true">"#
+        ));
+        // An empty value is left out rather than written as `android:value=""`
+        assert!(xml.contains(
+            r#"<meta-data android:name="android.support.FILE_PROVIDER_PATHS" android:resource="@xml/file_paths"/>"#
+        ));
+        assert!(xml.contains(r#"<meta-data android:name="version" android:value="2"/>"#));
+    }
+}
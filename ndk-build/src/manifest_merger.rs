@@ -0,0 +1,689 @@
+//! Merging of `AndroidManifest.xml` files, following the
+//! [merge rules](https://developer.android.com/build/manage-manifests#merge_rules)
+//! of the Android Gradle plugin.
+//!
+//! Elements are matched by their tag and `android:name` (or, for intent filters,
+//! their actions, categories and data), and the attributes and children of matching
+//! elements are merged recursively. Attributes that are declared with different
+//! values are a conflict, unless the higher-priority element resolves it with one
+//! of the following markers:
+//!
+//! - `tools:node="merge"` (default), `"merge-only-attributes"`, `"replace"`,
+//!   `"remove"`, `"removeAll"` or `"strict"`;
+//! - `tools:replace="android:attr,..."` to override the lower-priority values;
+//! - `tools:remove="android:attr,..."` to drop the lower-priority attributes.
+//!
+//! Markers are removed from the merged manifest.
+
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+const ANDROID_NS: &str = "http://schemas.android.com/apk/res/android";
+const TOOLS_NS: &str = "http://schemas.android.com/tools";
+
+/// Elements that occur at most once in their parent, and thus always match
+const SINGLETONS: &[&str] = &[
+    "application",
+    "uses-sdk",
+    "supports-screens",
+    "compatible-screens",
+    "queries",
+];
+
+/// Elements whose `android:name` is a class name, which may be relative to the
+/// `package` of their manifest
+const COMPONENTS: &[&str] = &[
+    "application",
+    "activity",
+    "activity-alias",
+    "service",
+    "receiver",
+    "provider",
+];
+
+/// Where an element or attribute was declared, to report conflicts
+#[derive(Clone, Debug)]
+pub(crate) struct Location {
+    source: String,
+    line: Option<usize>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}", self.source, line),
+            None => write!(f, "{}", self.source),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Attribute {
+    name: String,
+    value: String,
+    location: Location,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Element {
+    name: String,
+    attributes: Vec<Attribute>,
+    children: Vec<Element>,
+    location: Location,
+}
+
+impl Element {
+    /// Parses the root element of the manifest in `xml`, read from `source`.
+    ///
+    /// Attributes in the Android and tools namespaces are renamed to use the
+    /// `android:` and `tools:` prefixes. Line numbers are only recorded when
+    /// `lines` is set, as they are meaningless for generated manifests.
+    pub(crate) fn parse(xml: &str, source: &str, lines: bool) -> Result<Self, String> {
+        let mut reader = Reader::from_str(xml);
+        let mut prefixes = HashMap::new();
+        let mut stack: Vec<Element> = Vec::new();
+        let mut root = None;
+        let mut line = 1;
+        let mut position = 0;
+
+        loop {
+            let start = reader.buffer_position();
+            line += xml[position..start].matches('\n').count();
+            position = start;
+            let location = Location {
+                source: source.to_string(),
+                line: Some(line).filter(|_| lines),
+            };
+            let event = reader
+                .read_event()
+                .map_err(|e| format!("{location}: invalid XML: {e}"))?;
+            let (e, empty) = match event {
+                Event::Start(e) => (e, false),
+                Event::Empty(e) => (e, true),
+                Event::End(_) => {
+                    let element = stack.pop().unwrap();
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => root = Some(element),
+                    }
+                    continue;
+                }
+                Event::Eof => break,
+                _ => continue,
+            };
+
+            let element = Self::from_start(&e, &mut prefixes, location)?;
+            if !empty {
+                stack.push(element);
+            } else if let Some(parent) = stack.last_mut() {
+                parent.children.push(element);
+            } else {
+                root = Some(element);
+            }
+        }
+
+        let root = root.ok_or_else(|| format!("{source}: missing root element"))?;
+        if root.name != "manifest" {
+            return Err(format!(
+                "{}: root element is `<{}>` instead of `<manifest>`",
+                root.location, root.name
+            ));
+        }
+        Ok(root)
+    }
+
+    fn from_start(
+        e: &BytesStart<'_>,
+        prefixes: &mut HashMap<String, String>,
+        location: Location,
+    ) -> Result<Self, String> {
+        let mut attributes = Vec::new();
+        for attr in e.attributes() {
+            let attr = attr.map_err(|e| format!("{location}: invalid attribute: {e}"))?;
+            let name = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+            let value = attr
+                .unescape_value()
+                .map_err(|e| format!("{location}: invalid attribute value: {e}"))?
+                .into_owned();
+            if let Some(prefix) = name.strip_prefix("xmlns:") {
+                prefixes.insert(prefix.to_string(), value.clone());
+            }
+            attributes.push((name, value));
+        }
+
+        let canonical_prefix = |prefix: &str| match prefixes.get(prefix).map(String::as_str) {
+            Some(ANDROID_NS) => Some("android"),
+            Some(TOOLS_NS) => Some("tools"),
+            _ => None,
+        };
+        let rename = |name: &str| match name.split_once(':') {
+            Some(("xmlns", prefix)) => match canonical_prefix(prefix) {
+                Some(prefix) => format!("xmlns:{prefix}"),
+                None => name.to_string(),
+            },
+            Some((prefix, local)) => match canonical_prefix(prefix) {
+                Some(prefix) => format!("{prefix}:{local}"),
+                None => name.to_string(),
+            },
+            None => name.to_string(),
+        };
+
+        Ok(Self {
+            name: String::from_utf8_lossy(e.name().as_ref()).into_owned(),
+            attributes: attributes
+                .into_iter()
+                .map(|(name, value)| {
+                    let name = rename(&name);
+                    // Marker lists refer to attributes by their prefixed name
+                    let value = if name == "tools:replace" || name == "tools:remove" {
+                        value
+                            .split(',')
+                            .map(|attr| rename(attr.trim()))
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    } else {
+                        value
+                    };
+                    Attribute {
+                        name,
+                        value,
+                        location: location.clone(),
+                    }
+                })
+                .collect(),
+            children: Vec::new(),
+            location,
+        })
+    }
+
+    pub(crate) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attr| attr.name == name)
+            .map(|attr| attr.value.as_str())
+    }
+
+    fn tools_node(&self) -> Option<&str> {
+        self.attribute("tools:node")
+    }
+
+    /// Attribute names listed in the marker attribute `name`
+    fn marker_list(&self, name: &str) -> HashSet<&str> {
+        self.attribute(name)
+            .map(|list| list.split(',').map(str::trim).collect())
+            .unwrap_or_default()
+    }
+
+    /// Identifies the element among its siblings with the same tag
+    fn key(&self) -> Option<String> {
+        if self.name == "intent-filter" {
+            let mut children = self
+                .children
+                .iter()
+                .map(|c| c.content())
+                .collect::<Vec<_>>();
+            children.sort();
+            return Some(children.concat());
+        }
+        self.attribute("android:name")
+            .or_else(|| self.attribute("android:glEsVersion"))
+            .map(str::to_string)
+            .or_else(|| SINGLETONS.contains(&self.name.as_str()).then(String::new))
+    }
+
+    /// Canonical form of the element without markers, to compare elements that
+    /// have no key
+    fn content(&self) -> String {
+        let mut attributes = self
+            .attributes
+            .iter()
+            .filter(|attr| !attr.name.starts_with("tools:"))
+            .map(|attr| format!(" {}={:?}", attr.name, attr.value))
+            .collect::<Vec<_>>();
+        attributes.sort();
+        let mut children = self
+            .children
+            .iter()
+            .map(|c| c.content())
+            .collect::<Vec<_>>();
+        children.sort();
+        format!(
+            "<{}{}>{}</{}>",
+            self.name,
+            attributes.concat(),
+            children.concat(),
+            self.name
+        )
+    }
+
+    /// Whether `other` is the same element as this one, comparing the class names of
+    /// components once they are qualified with `package`
+    fn matches(&self, other: &Self, package: &str) -> bool {
+        let key = |element: &Self| {
+            let key = element.key()?;
+            if COMPONENTS.contains(&element.name.as_str()) && !key.is_empty() {
+                Some(qualify_class_name(package, &key))
+            } else {
+                Some(key)
+            }
+        };
+        self.name == other.name
+            && match (key(self), key(other)) {
+                (Some(key), Some(other_key)) => key == other_key,
+                (None, None) => self.content() == other.content(),
+                _ => false,
+            }
+    }
+
+    /// Removes all markers, and the elements they mark for removal
+    fn strip_markers(&mut self) {
+        self.attributes
+            .retain(|attr| !attr.name.starts_with("tools:") && attr.name != "xmlns:tools");
+        self.children
+            .retain(|c| !matches!(c.tools_node(), Some("remove") | Some("removeAll")));
+        for child in &mut self.children {
+            child.strip_markers();
+        }
+    }
+
+    /// Makes the relative class names of components, such as `.Foo` or `Foo`, absolute
+    /// by prefixing them with `package`
+    fn qualify_class_names(&mut self, package: &str) {
+        if COMPONENTS.contains(&self.name.as_str()) {
+            for attr in &mut self.attributes {
+                if attr.name == "android:name" || attr.name == "android:targetActivity" {
+                    attr.value = qualify_class_name(package, &attr.value);
+                }
+            }
+        }
+        for child in &mut self.children {
+            child.qualify_class_names(package);
+        }
+    }
+
+    /// Merges the manifest of a library into this manifest, which has priority.
+    ///
+    /// Relative class names in the library are resolved against its `package`. The
+    /// `package` and other attributes of the library `<manifest>` and its
+    /// `<uses-sdk>` are not merged, but the library may not require a higher
+    /// `minSdkVersion`.
+    pub(crate) fn merge_library(&mut self, mut library: Self) -> Result<(), String> {
+        library.strip_markers();
+        if let Some(package) = library.attribute("package").map(str::to_string) {
+            library.qualify_class_names(&package);
+        }
+        library
+            .attributes
+            .retain(|attr| attr.name.starts_with("xmlns:"));
+        if let Some(index) = library.children.iter().position(|c| c.name == "uses-sdk") {
+            let uses_sdk = library.children.remove(index);
+            let min_sdk = |uses_sdk: &Self| {
+                uses_sdk
+                    .attribute("android:minSdkVersion")
+                    .and_then(|v| v.parse::<u32>().ok())
+            };
+            let app_min_sdk = self
+                .children
+                .iter()
+                .find(|c| c.name == "uses-sdk")
+                .and_then(min_sdk);
+            if let (Some(library_min_sdk), Some(app_min_sdk)) = (min_sdk(&uses_sdk), app_min_sdk) {
+                if library_min_sdk > app_min_sdk {
+                    return Err(format!(
+                        "{}: the library requires `minSdkVersion` {} but the app declares {}",
+                        uses_sdk.location, library_min_sdk, app_min_sdk
+                    ));
+                }
+            }
+        }
+        let package = self.attribute("package").unwrap_or_default().to_string();
+        self.merge(library, &package)
+    }
+
+    /// Merges the manifest `overlay`, which has priority over this manifest, into it.
+    ///
+    /// Relative class names on both sides are resolved against the `package` of the
+    /// overlay, or else of this manifest, to find the components they both declare.
+    pub(crate) fn merge_overlay(&mut self, mut overlay: Self) -> Result<(), String> {
+        let package = overlay
+            .attribute("package")
+            .or_else(|| self.attribute("package"))
+            .unwrap_or_default()
+            .to_string();
+        std::mem::swap(self, &mut overlay);
+        self.merge(overlay, &package)
+    }
+
+    /// Merges the lower-priority element `other` into this element, in a manifest whose
+    /// relative class names belong to `package`
+    fn merge(&mut self, mut other: Self, package: &str) -> Result<(), String> {
+        other.strip_markers();
+        let merge_children = match self.tools_node() {
+            None | Some("merge") => true,
+            Some("merge-only-attributes") => false,
+            Some("replace") | Some("remove") | Some("removeAll") => return Ok(()),
+            Some("strict") => {
+                return if self.content() == other.content() {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "{}: `<{}>` is marked `tools:node=\"strict\"` but differs from the one at {}",
+                        self.location, self.name, other.location
+                    ))
+                };
+            }
+            Some(node) => {
+                return Err(format!(
+                    "{}: unknown `tools:node` value `{}`",
+                    self.location, node
+                ))
+            }
+        };
+
+        let replace = self.marker_list("tools:replace");
+        let remove = self.marker_list("tools:remove");
+        let mut attributes = Vec::new();
+        let is_component = COMPONENTS.contains(&self.name.as_str());
+        let same_value = |own: &Attribute, attr: &Attribute| {
+            own.value == attr.value
+                || is_component
+                    && (attr.name == "android:name" || attr.name == "android:targetActivity")
+                    && qualify_class_name(package, &own.value)
+                        == qualify_class_name(package, &attr.value)
+        };
+        for attr in other.attributes {
+            if remove.contains(attr.name.as_str()) {
+                continue;
+            }
+            match self.attributes.iter().find(|a| a.name == attr.name) {
+                None => attributes.push(attr),
+                Some(own) if same_value(own, &attr) || replace.contains(attr.name.as_str()) => {}
+                Some(own) => {
+                    return Err(format!(
+                        "attribute `{name}` of `<{tag}>` is `{}` at {} but `{}` at {}; add `tools:replace=\"{name}\"` to the element at {} to override it",
+                        own.value,
+                        own.location,
+                        attr.value,
+                        attr.location,
+                        self.location,
+                        name = attr.name,
+                        tag = self.name,
+                    ))
+                }
+            }
+        }
+        self.attributes.extend(attributes);
+
+        if !merge_children {
+            return Ok(());
+        }
+        let own_children = self.children.len();
+        for child in other.children {
+            let remove_all = self.children[..own_children]
+                .iter()
+                .any(|c| c.name == child.name && c.tools_node() == Some("removeAll"));
+            if remove_all {
+                continue;
+            }
+            match self.children[..own_children]
+                .iter_mut()
+                .find(|c| c.matches(&child, package))
+            {
+                Some(own) => own.merge(child, package)?,
+                None => self.children.push(child),
+            }
+        }
+        Ok(())
+    }
+
+    /// Name of the enabled `<activity>` or `<activity-alias>` with a `MAIN` and `LAUNCHER`
+    /// intent filter in the `<application>` of this manifest, preferring `preferred` when
+    /// there are several
+    pub(crate) fn launcher_activity(&self, preferred: Option<&str>) -> Option<&str> {
+        let application = self.children.iter().find(|c| c.name == "application")?;
+        let has_filter_entry = |filter: &Self, tag: &str, name: &str| {
+            filter
+                .children
+                .iter()
+                .any(|c| c.name == tag && c.attribute("android:name") == Some(name))
+        };
+        let mut launchers = application
+            .children
+            .iter()
+            .filter(|c| c.name == "activity" || c.name == "activity-alias")
+            .filter(|c| c.attribute("android:enabled") != Some("false"))
+            .filter(|c| {
+                c.children.iter().any(|filter| {
+                    filter.name == "intent-filter"
+                        && has_filter_entry(filter, "action", "android.intent.action.MAIN")
+                        && has_filter_entry(filter, "category", "android.intent.category.LAUNCHER")
+                })
+            })
+            .filter_map(|c| c.attribute("android:name"));
+        let first = launchers.next()?;
+        Some(
+            std::iter::once(first)
+                .chain(launchers)
+                .find(|&name| Some(name) == preferred)
+                .unwrap_or(first),
+        )
+    }
+
+    /// Serializes the element, removing any markers that are left
+    pub(crate) fn to_xml(&self) -> String {
+        let mut element = self.clone();
+        element.strip_markers();
+        if element.attribute("xmlns:android").is_none() {
+            element.attributes.insert(
+                0,
+                Attribute {
+                    name: "xmlns:android".to_string(),
+                    value: ANDROID_NS.to_string(),
+                    location: element.location.clone(),
+                },
+            );
+        }
+        let mut xml = String::new();
+        element.write(&mut xml, 0);
+        xml
+    }
+
+    fn write(&self, xml: &mut String, depth: usize) {
+        let indent = "    ".repeat(depth);
+        xml.push_str(&format!("{indent}<{}", self.name));
+        for attr in &self.attributes {
+            xml.push_str(&format!(" {}=\"{}\"", attr.name, escape(&attr.value)));
+        }
+        if self.children.is_empty() {
+            xml.push_str(" />\n");
+            return;
+        }
+        xml.push_str(">\n");
+        for child in &self.children {
+            child.write(xml, depth + 1);
+        }
+        xml.push_str(&format!("{indent}</{}>\n", self.name));
+    }
+}
+
+/// Makes the relative class name `name`, such as `.Foo` or `Foo`, absolute by prefixing
+/// it with `package`
+fn qualify_class_name(package: &str, name: &str) -> String {
+    if name.starts_with('.') {
+        format!("{package}{name}")
+    } else if !name.contains('.') {
+        format!("{package}.{name}")
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENERATED: &str = r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android" package="com.example.app"><uses-sdk android:minSdkVersion="23"/><uses-permission android:name="android.permission.INTERNET"/><application android:label="App" android:hasCode="false"><activity android:name="android.app.NativeActivity" android:exported="true"><intent-filter><action android:name="android.intent.action.MAIN"/><category android:name="android.intent.category.LAUNCHER"/></intent-filter></activity></application></manifest>"#;
+
+    fn generated() -> Element {
+        Element::parse(GENERATED, "the generated manifest", false).unwrap()
+    }
+
+    #[test]
+    fn roundtrips_generated_manifest() {
+        let mut manifest = crate::manifest::AndroidManifest::default();
+        manifest.package = "com.example.app".to_string();
+        manifest.application.label = "A & B".to_string();
+        let generated = quick_xml::se::to_string(&manifest).unwrap();
+        let xml = Element::parse(&generated, "the generated manifest", false)
+            .unwrap()
+            .to_xml();
+        assert!(xml.contains(r#"android:label="A &amp; B""#));
+        assert!(xml.contains(r#"<activity android:configChanges="#));
+    }
+
+    #[test]
+    fn finds_launcher_activity() {
+        let manifest = r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android" package="com.example.app"><application>
+    <activity android:name=".Splash"><intent-filter><action android:name="android.intent.action.MAIN"/><category android:name="android.intent.category.LAUNCHER"/></intent-filter></activity>
+    <activity android:name="android.app.NativeActivity"><intent-filter><action android:name="android.intent.action.MAIN"/><category android:name="android.intent.category.LAUNCHER"/></intent-filter></activity>
+    <activity-alias android:name=".Alt" android:enabled="false"><intent-filter><action android:name="android.intent.action.MAIN"/><category android:name="android.intent.category.LAUNCHER"/></intent-filter></activity-alias>
+</application></manifest>"#;
+        let manifest = Element::parse(manifest, "AndroidManifest.xml", true).unwrap();
+        assert_eq!(manifest.launcher_activity(None), Some(".Splash"));
+        assert_eq!(
+            manifest.launcher_activity(Some("android.app.NativeActivity")),
+            Some("android.app.NativeActivity")
+        );
+        assert_eq!(manifest.launcher_activity(Some(".Alt")), Some(".Splash"));
+        assert_eq!(
+            generated().launcher_activity(None),
+            Some("android.app.NativeActivity")
+        );
+    }
+
+    #[test]
+    fn merges_overlay_with_markers() {
+        let overlay = r#"<?xml version="1.0" encoding="utf-8"?>
+<manifest xmlns:a="http://schemas.android.com/apk/res/android" xmlns:t="http://schemas.android.com/tools">
+    <uses-permission a:name="android.permission.INTERNET" t:node="remove" />
+    <application a:label="Overlay" a:hasCode="true" t:replace="a:label, a:hasCode">
+        <service a:name=".Sync" a:exported="false" />
+        <activity a:name="android.app.NativeActivity" a:exported="true">
+            <intent-filter>
+                <category a:name="android.intent.category.LAUNCHER" />
+                <action a:name="android.intent.action.MAIN" />
+            </intent-filter>
+            <meta-data a:name="android.app.lib_name" a:value="app" />
+        </activity>
+    </application>
+</manifest>"#;
+        let mut manifest = generated();
+        manifest
+            .merge_overlay(Element::parse(overlay, "overlay.xml", true).unwrap())
+            .unwrap();
+        assert_eq!(
+            manifest.to_xml(),
+            r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android" package="com.example.app">
+    <application android:label="Overlay" android:hasCode="true">
+        <service android:name=".Sync" android:exported="false" />
+        <activity android:name="android.app.NativeActivity" android:exported="true">
+            <intent-filter>
+                <category android:name="android.intent.category.LAUNCHER" />
+                <action android:name="android.intent.action.MAIN" />
+            </intent-filter>
+            <meta-data android:name="android.app.lib_name" android:value="app" />
+        </activity>
+    </application>
+    <uses-sdk android:minSdkVersion="23" />
+</manifest>
+"#
+        );
+    }
+
+    #[test]
+    fn matches_relative_class_names_of_overlay() {
+        let generated = r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android" package="com.example.app"><application><activity android:name="com.example.app.Foo"/><service android:name="Sync"/></application></manifest>"#;
+        let overlay = r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android">
+    <application>
+        <activity android:name=".Foo" android:exported="false" />
+        <service android:name="com.example.app.Sync" android:exported="false" />
+    </application>
+</manifest>"#;
+        let mut manifest = Element::parse(generated, "the generated manifest", false).unwrap();
+        manifest
+            .merge_overlay(Element::parse(overlay, "overlay.xml", true).unwrap())
+            .unwrap();
+        assert_eq!(
+            manifest.to_xml(),
+            r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android" package="com.example.app">
+    <application>
+        <activity android:name=".Foo" android:exported="false" />
+        <service android:name="com.example.app.Sync" android:exported="false" />
+    </application>
+</manifest>
+"#
+        );
+        assert_eq!(
+            qualify_class_name("com.example.app", "Foo$Inner"),
+            "com.example.app.Foo$Inner"
+        );
+        assert_eq!(
+            qualify_class_name("com.example.app", "com.vendor.Foo"),
+            "com.vendor.Foo"
+        );
+    }
+
+    #[test]
+    fn reports_conflicts_with_location() {
+        let overlay = r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android">
+    <application
+        android:label="Overlay" />
+</manifest>"#;
+        let mut manifest = generated();
+        let err = manifest
+            .merge_overlay(Element::parse(overlay, "overlay.xml", true).unwrap())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "attribute `android:label` of `<application>` is `Overlay` at overlay.xml:2 but `App` at the generated manifest; add `tools:replace=\"android:label\"` to the element at overlay.xml:2 to override it"
+        );
+    }
+
+    #[test]
+    fn merges_library() {
+        let library = r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android" xmlns:tools="http://schemas.android.com/tools" package="com.vendor.sdk">
+    <uses-sdk android:minSdkVersion="21" />
+    <uses-permission android:name="android.permission.INTERNET" />
+    <uses-permission android:name="android.permission.WAKE_LOCK" tools:node="remove" />
+    <application>
+        <provider android:name="com.vendor.sdk.Init" android:authorities="com.example.app.init" />
+        <service android:name=".SyncService" />
+        <receiver android:name="BootReceiver" />
+        <activity android:name=".ui.SettingsActivity" />
+        <activity-alias android:name=".Settings" android:targetActivity=".ui.SettingsActivity" />
+    </application>
+</manifest>"#;
+        let mut manifest = generated();
+        manifest
+            .merge_library(Element::parse(library, "sdk.aar", true).unwrap())
+            .unwrap();
+        let xml = manifest.to_xml();
+        assert!(xml.contains(r#"<provider android:name="com.vendor.sdk.Init""#));
+        assert!(xml.contains(r#"<service android:name="com.vendor.sdk.SyncService" />"#));
+        assert!(xml.contains(r#"<receiver android:name="com.vendor.sdk.BootReceiver" />"#));
+        assert!(xml.contains(r#"<activity android:name="com.vendor.sdk.ui.SettingsActivity" />"#));
+        assert!(xml.contains(r#"<activity-alias android:name="com.vendor.sdk.Settings" android:targetActivity="com.vendor.sdk.ui.SettingsActivity" />"#));
+        assert!(!xml.contains("com.vendor.sdk\""));
+        assert!(!xml.contains("WAKE_LOCK"));
+        assert_eq!(xml.matches("android.permission.INTERNET").count(), 1);
+
+        let library = library.replace("\"21\"", "\"26\"");
+        let err = generated()
+            .merge_library(Element::parse(&library, "sdk.aar", true).unwrap())
+            .unwrap_err();
+        assert!(err.starts_with("sdk.aar:2: the library requires `minSdkVersion` 26"));
+    }
+}
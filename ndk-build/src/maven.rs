@@ -0,0 +1,720 @@
+//! Resolution of AARs and JARs, along with their transitive dependencies, from
+//! Google's Maven repository and Maven Central.
+//!
+//! Artifacts and their POM files are downloaded with `curl` into a cache in the
+//! Android user home, so that only the first build needs network access. Every
+//! file is checked against the `.sha256` or `.sha1` checksum published next to it,
+//! both when it is downloaded and when it is read from the cache. As the checksums
+//! come from the same repository, this detects corrupted downloads and cache
+//! entries but does not authenticate the files.
+//!
+//! When a library is depended on in several versions the highest one is used,
+//! like Gradle does.
+//!
+//! POMs inherit properties, dependencies and managed versions from their parent
+//! POMs and imported BOMs, and `${...}` properties in dependencies are substituted.
+//! Other POM features, such as profiles, are not supported.
+
+use crate::error::NdkError;
+use serde::Deserialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const REPOSITORIES: &[&str] = &[
+    "https://dl.google.com/android/maven2",
+    "https://repo1.maven.org/maven2",
+];
+
+/// Checksums published next to every file in a Maven repository, by preference
+#[derive(Clone, Copy, Debug)]
+enum Checksum {
+    Sha256,
+    Sha1,
+}
+
+impl Checksum {
+    const ALL: [Self; 2] = [Self::Sha256, Self::Sha1];
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha1 => "sha1",
+        }
+    }
+
+    fn hex_digest(self, data: &[u8]) -> String {
+        let digest = match self {
+            Self::Sha256 => Sha256::digest(data).to_vec(),
+            Self::Sha1 => Sha1::digest(data).to_vec(),
+        };
+        digest.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// Checks `data` against the contents of a checksum file, which holds the hex
+    /// digest optionally followed by the file name. Returns the actual digest on a
+    /// mismatch.
+    fn verify(self, data: &[u8], checksum_file: &str) -> Result<(), String> {
+        let expected = checksum_file
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let actual = self.hex_digest(data);
+        if actual == expected {
+            Ok(())
+        } else {
+            Err(actual)
+        }
+    }
+}
+
+/// A `group:artifact:version` Maven coordinate
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Coordinate {
+    pub group: String,
+    pub artifact: String,
+    pub version: String,
+}
+
+impl Coordinate {
+    pub fn new(group: &str, artifact: &str, version: &str) -> Self {
+        Self {
+            group: group.to_string(),
+            artifact: artifact.to_string(),
+            version: version.to_string(),
+        }
+    }
+
+    /// Path of the artifact directory relative to the repository root
+    fn dir(&self) -> String {
+        format!(
+            "{}/{}/{}",
+            self.group.replace('.', "/"),
+            self.artifact,
+            self.version
+        )
+    }
+
+    fn file_name(&self, extension: &str) -> String {
+        format!("{}-{}.{}", self.artifact, self.version, extension)
+    }
+}
+
+impl fmt::Display for Coordinate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.group, self.artifact, self.version)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Pom {
+    packaging: Option<String>,
+    parent: Option<Parent>,
+    #[serde(default)]
+    properties: BTreeMap<String, String>,
+    #[serde(default)]
+    dependency_management: DependencyManagement,
+    #[serde(default)]
+    dependencies: Dependencies,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Parent {
+    group_id: String,
+    artifact_id: String,
+    version: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct DependencyManagement {
+    #[serde(default)]
+    dependencies: Dependencies,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Dependencies {
+    #[serde(default)]
+    dependency: Vec<Dependency>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Dependency {
+    group_id: String,
+    artifact_id: String,
+    version: Option<String>,
+    scope: Option<String>,
+    optional: Option<String>,
+}
+
+impl Dependency {
+    /// Whether the dependency is needed at runtime
+    fn is_runtime(&self) -> bool {
+        matches!(
+            self.scope.as_deref(),
+            None | Some("compile") | Some("runtime")
+        ) && self.optional.as_deref() != Some("true")
+    }
+}
+
+/// Downloads and caches Maven artifacts
+pub struct Repository {
+    cache_dir: PathBuf,
+}
+
+impl Repository {
+    pub fn new(cache_dir: PathBuf) -> Self {
+        Self { cache_dir }
+    }
+
+    /// Downloads `file` of `coordinate` into the cache along with its checksum, unless
+    /// it was downloaded before and still matches that checksum
+    fn fetch(&self, coordinate: &Coordinate, file: &str) -> Result<PathBuf, NdkError> {
+        let dir = self.cache_dir.join(coordinate.dir());
+        let path = dir.join(file);
+        if path.exists() {
+            if verify_cached(&path)? {
+                return Ok(path);
+            }
+            remove_cached(&path)?;
+        }
+        fs::create_dir_all(&dir)?;
+        let partial = dir.join(format!("{file}.part"));
+        for repository in REPOSITORIES {
+            let url = format!("{}/{}/{}", repository, coordinate.dir(), file);
+            if !download(&url, &partial)? {
+                continue;
+            }
+            let data = fs::read(&partial).map_err(|e| NdkError::IoPathError(partial.clone(), e))?;
+            for checksum in Checksum::ALL {
+                let checksum_path = dir.join(format!("{file}.{}", checksum.extension()));
+                let checksum_partial = dir.join(format!("{file}.{}.part", checksum.extension()));
+                if !download(
+                    &format!("{url}.{}", checksum.extension()),
+                    &checksum_partial,
+                )? {
+                    continue;
+                }
+                let expected = fs::read_to_string(&checksum_partial)
+                    .map_err(|e| NdkError::IoPathError(checksum_partial.clone(), e))?;
+                if let Err(actual) = checksum.verify(&data, &expected) {
+                    fs::remove_file(&partial).map_err(|e| NdkError::IoPathError(partial, e))?;
+                    fs::remove_file(&checksum_partial)
+                        .map_err(|e| NdkError::IoPathError(checksum_partial, e))?;
+                    return Err(NdkError::MavenChecksumMismatch {
+                        url,
+                        expected: expected.trim().to_string(),
+                        actual,
+                    });
+                }
+                fs::rename(&checksum_partial, &checksum_path)
+                    .map_err(|e| NdkError::IoPathError(checksum_path, e))?;
+                fs::rename(&partial, &path).map_err(|e| NdkError::IoPathError(path.clone(), e))?;
+                return Ok(path);
+            }
+            fs::remove_file(&partial).map_err(|e| NdkError::IoPathError(partial, e))?;
+            return Err(NdkError::MavenChecksumNotFound(url));
+        }
+        Err(NdkError::MavenArtifactNotFound(format!(
+            "{coordinate} ({file})"
+        )))
+    }
+
+    /// Reads the POM of `coordinate` merged with its parent POMs, whose properties,
+    /// managed versions and dependencies it overrides. `${...}` properties are not
+    /// substituted yet.
+    fn inherited_pom(&self, coordinate: &Coordinate) -> Result<Pom, NdkError> {
+        let path = self.fetch(coordinate, &coordinate.file_name("pom"))?;
+        let xml = fs::read_to_string(&path).map_err(|e| NdkError::IoPathError(path, e))?;
+        let mut pom: Pom = quick_xml::de::from_str(&xml)?;
+        if let Some(parent) = &pom.parent {
+            let parent = self.inherited_pom(&Coordinate::new(
+                &parent.group_id,
+                &parent.artifact_id,
+                &parent.version,
+            ))?;
+            for (name, value) in parent.properties {
+                pom.properties.entry(name).or_insert(value);
+            }
+            pom.dependency_management
+                .dependencies
+                .dependency
+                .extend(parent.dependency_management.dependencies.dependency);
+            pom.dependencies
+                .dependency
+                .extend(parent.dependencies.dependency);
+        }
+        Ok(pom)
+    }
+
+    /// Reads the POM of `coordinate`, keeping only its runtime dependencies with
+    /// their versions resolved from properties, its parent POMs and imported BOMs.
+    /// The managed versions of the returned POM are resolved likewise.
+    fn pom(&self, coordinate: &Coordinate) -> Result<Pom, NdkError> {
+        let mut pom = self.inherited_pom(coordinate)?;
+        let unsupported =
+            |feature: String| NdkError::UnsupportedPom(coordinate.to_string(), feature);
+
+        let mut properties = std::mem::take(&mut pom.properties);
+        for prefix in ["project", "pom"] {
+            properties.insert(format!("{prefix}.groupId"), coordinate.group.clone());
+            properties.insert(format!("{prefix}.artifactId"), coordinate.artifact.clone());
+            properties.insert(format!("{prefix}.version"), coordinate.version.clone());
+        }
+        if let Some(parent) = &pom.parent {
+            properties.insert("project.parent.groupId".into(), parent.group_id.clone());
+            properties.insert("project.parent.version".into(), parent.version.clone());
+        }
+        let interpolate = |value: &str| {
+            interpolate(value, &properties)
+                .map_err(|name| unsupported(format!("unresolved property `${{{name}}}`")))
+        };
+        let interpolate_dependency = |dependency: &mut Dependency| -> Result<(), NdkError> {
+            dependency.group_id = interpolate(&dependency.group_id)?;
+            dependency.artifact_id = interpolate(&dependency.artifact_id)?;
+            let values = vec![
+                &mut dependency.version,
+                &mut dependency.scope,
+                &mut dependency.optional,
+            ];
+            for value in values.into_iter().flatten() {
+                *value = interpolate(value)?;
+            }
+            Ok(())
+        };
+
+        // Managed versions declared in this POM and its parents take precedence over
+        // those of imported BOMs
+        let mut managed = BTreeMap::<(String, String), Dependency>::new();
+        let mut boms = Vec::new();
+        for mut dependency in std::mem::take(&mut pom.dependency_management.dependencies.dependency)
+        {
+            interpolate_dependency(&mut dependency)?;
+            let key = (dependency.group_id.clone(), dependency.artifact_id.clone());
+            if dependency.scope.as_deref() == Some("import") {
+                let version = dependency.version.as_deref().ok_or_else(|| {
+                    unsupported(format!(
+                        "imported BOM `{}:{}` without a version",
+                        key.0, key.1
+                    ))
+                })?;
+                boms.push(self.pom(&Coordinate::new(&key.0, &key.1, version))?);
+            } else if dependency.version.is_some() {
+                managed.entry(key).or_insert(dependency);
+            }
+        }
+        for bom in boms {
+            for dependency in bom.dependency_management.dependencies.dependency {
+                let key = (dependency.group_id.clone(), dependency.artifact_id.clone());
+                managed.entry(key).or_insert(dependency);
+            }
+        }
+
+        let mut dependencies = Vec::<Dependency>::new();
+        for mut dependency in std::mem::take(&mut pom.dependencies.dependency) {
+            interpolate_dependency(&mut dependency)?;
+            let key = (dependency.group_id.clone(), dependency.artifact_id.clone());
+            if dependencies
+                .iter()
+                .any(|d| d.group_id == key.0 && d.artifact_id == key.1)
+            {
+                continue;
+            }
+            if let Some(managed) = managed.get(&key) {
+                dependency.version = dependency.version.or_else(|| managed.version.clone());
+                dependency.scope = dependency.scope.or_else(|| managed.scope.clone());
+            }
+            if !dependency.is_runtime() {
+                continue;
+            }
+            if dependency.version.is_none() {
+                return Err(unsupported(format!(
+                    "no version for `{}:{}` in `<dependencies>`, `<dependencyManagement>` or the parent POMs",
+                    key.0, key.1
+                )));
+            }
+            dependencies.push(dependency);
+        }
+
+        pom.dependencies.dependency = dependencies;
+        pom.dependency_management.dependencies.dependency = managed.into_values().collect();
+        Ok(pom)
+    }
+
+    /// Resolves `roots` and their runtime dependencies, returning the paths of the
+    /// downloaded `.aar` and `.jar` files
+    pub fn resolve(&self, roots: &[Coordinate]) -> Result<Vec<PathBuf>, NdkError> {
+        // Highest requested version of every `group:artifact`, and the POM of every
+        // resolved coordinate
+        let mut versions = BTreeMap::<(String, String), String>::new();
+        let mut poms = BTreeMap::new();
+        loop {
+            let mut changed = false;
+            let mut reached = Vec::new();
+            let mut queue = roots.iter().cloned().collect::<VecDeque<_>>();
+            while let Some(mut coordinate) = queue.pop_front() {
+                let key = (coordinate.group.clone(), coordinate.artifact.clone());
+                match versions.get(&key) {
+                    Some(version) if compare_versions(version, &coordinate.version).is_ge() => {
+                        coordinate.version = version.clone();
+                    }
+                    _ => {
+                        changed |= versions.contains_key(&key);
+                        versions.insert(key, coordinate.version.clone());
+                    }
+                }
+                if reached.contains(&coordinate) {
+                    continue;
+                }
+                if !poms.contains_key(&coordinate) {
+                    let pom = self.pom(&coordinate)?;
+                    poms.insert(coordinate.clone(), pom);
+                }
+                for dependency in &poms[&coordinate].dependencies.dependency {
+                    if !dependency.is_runtime() {
+                        continue;
+                    }
+                    // `Repository::pom` only keeps runtime dependencies with a version
+                    let version = dependency.version.as_deref().unwrap();
+                    queue.push_back(Coordinate::new(
+                        &dependency.group_id,
+                        &dependency.artifact_id,
+                        strip_version_range(version),
+                    ));
+                }
+                reached.push(coordinate);
+            }
+            // Traverse again when a version was raised, as the dependencies of the
+            // previously resolved version may no longer be needed
+            if !changed {
+                let mut files = Vec::new();
+                for coordinate in reached
+                    .iter()
+                    .filter(|c| !is_merged_into_stdlib(c, &versions))
+                {
+                    let extension = match poms[coordinate].packaging.as_deref() {
+                        Some("aar") => "aar",
+                        _ => "jar",
+                    };
+                    files.push(self.fetch(coordinate, &coordinate.file_name(extension))?);
+                }
+                return Ok(files);
+            }
+        }
+    }
+}
+
+/// Downloads `url` to `output`, returning `false` if the repository does not have it
+fn download(url: &str, output: &Path) -> Result<bool, NdkError> {
+    let mut curl = Command::new(bin!("curl"));
+    curl.arg("--fail")
+        .arg("--silent")
+        .arg("--show-error")
+        .arg("--location")
+        .arg("--output")
+        .arg(output)
+        .arg(url);
+    let status = curl
+        .status()
+        .map_err(|e| NdkError::IoPathError(PathBuf::from("curl"), e))?;
+    Ok(status.success())
+}
+
+/// Whether the cached file at `path` matches the checksum that was downloaded with it
+fn verify_cached(path: &Path) -> Result<bool, NdkError> {
+    for checksum in Checksum::ALL {
+        let checksum_path = PathBuf::from(format!("{}.{}", path.display(), checksum.extension()));
+        if let Ok(expected) = fs::read_to_string(&checksum_path) {
+            let data = fs::read(path).map_err(|e| NdkError::IoPathError(path.to_owned(), e))?;
+            return Ok(checksum.verify(&data, &expected).is_ok());
+        }
+    }
+    Ok(false)
+}
+
+/// Removes the cached file at `path` and its checksums
+fn remove_cached(path: &Path) -> Result<(), NdkError> {
+    fs::remove_file(path).map_err(|e| NdkError::IoPathError(path.to_owned(), e))?;
+    for checksum in Checksum::ALL {
+        let checksum_path = PathBuf::from(format!("{}.{}", path.display(), checksum.extension()));
+        if checksum_path.exists() {
+            fs::remove_file(&checksum_path).map_err(|e| NdkError::IoPathError(checksum_path, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// `kotlin-stdlib-jdk7` and `-jdk8` are empty since Kotlin 1.8, which merged
+/// their classes into `kotlin-stdlib`. Older versions would define the same
+/// classes twice, which `d8` rejects.
+fn is_merged_into_stdlib(
+    coordinate: &Coordinate,
+    versions: &BTreeMap<(String, String), String>,
+) -> bool {
+    let stdlib = (
+        "org.jetbrains.kotlin".to_string(),
+        "kotlin-stdlib".to_string(),
+    );
+    coordinate.group == stdlib.0
+        && (coordinate.artifact == "kotlin-stdlib-jdk7"
+            || coordinate.artifact == "kotlin-stdlib-jdk8")
+        && versions
+            .get(&stdlib)
+            .is_some_and(|version| compare_versions(version, "1.8").is_ge())
+}
+
+/// Substitutes `${name}` properties in `value`, including properties referred to by
+/// other properties. Returns the name of the first property that can't be resolved.
+fn interpolate(value: &str, properties: &BTreeMap<String, String>) -> Result<String, String> {
+    let mut value = value.to_string();
+    // Bound the number of substitutions to reject properties that refer to themselves
+    for _ in 0..100 {
+        let start = match value.find("${") {
+            Some(start) => start,
+            None => return Ok(value),
+        };
+        let end = value[start..]
+            .find('}')
+            .ok_or_else(|| value[start + 2..].to_string())?
+            + start;
+        let name = &value[start + 2..end];
+        let property = properties.get(name).ok_or_else(|| name.to_string())?;
+        value.replace_range(start..=end, &property.clone());
+    }
+    let start = value.find("${").unwrap();
+    let end = value[start..]
+        .find('}')
+        .map_or(value.len(), |end| start + end);
+    Err(value[start + 2..end].to_string())
+}
+
+/// Turns a hard requirement such as `[1.2.0]` into its version
+fn strip_version_range(version: &str) -> &str {
+    version.trim_start_matches('[').trim_end_matches(']')
+}
+
+/// Compares Maven versions by their numeric components, ordering pre-releases such
+/// as `1.0.0-alpha01` before the release
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let split = |version: &str| {
+        let (release, qualifier) = version.split_once('-').unwrap_or((version, ""));
+        let numbers = release
+            .split('.')
+            .map(|n| n.parse::<u64>().unwrap_or(0))
+            .collect::<Vec<_>>();
+        (numbers, qualifier.to_string())
+    };
+    let (a_numbers, a_qualifier) = split(a);
+    let (b_numbers, b_qualifier) = split(b);
+    let len = a_numbers.len().max(b_numbers.len());
+    let number = |numbers: &[u64], i| numbers.get(i).copied().unwrap_or(0);
+    (0..len)
+        .map(|i| number(&a_numbers, i).cmp(&number(&b_numbers, i)))
+        .find(|o| o.is_ne())
+        .unwrap_or_else(|| match (a_qualifier.is_empty(), b_qualifier.is_empty()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => a_qualifier.cmp(&b_qualifier),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_order() {
+        assert_eq!(compare_versions("1.6.1", "1.6.1"), Ordering::Equal);
+        assert_eq!(compare_versions("1.10.0", "1.9.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1.6", "1.6.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.7.0-alpha01", "1.7.0"), Ordering::Less);
+        assert_eq!(
+            compare_versions("1.7.0-beta01", "1.7.0-alpha02"),
+            Ordering::Greater
+        );
+        assert_eq!(strip_version_range("[1.2.0]"), "1.2.0");
+    }
+
+    #[test]
+    fn checksums() {
+        let sha1 = "a9993e364706816aba3e25717850c26c9cd0d89d";
+        assert_eq!(Checksum::Sha1.verify(b"abc", sha1), Ok(()));
+        assert_eq!(
+            Checksum::Sha1.verify(b"abc", &format!("{}  abc.jar\n", sha1.to_uppercase())),
+            Ok(())
+        );
+        assert_eq!(
+            Checksum::Sha256.verify(b"abc", sha1),
+            Err("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string())
+        );
+
+        let dir = std::env::temp_dir().join(format!("ndk-build-maven-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let jar = dir.join("lib-1.0.jar");
+        fs::write(&jar, b"abc").unwrap();
+        assert!(!verify_cached(&jar).unwrap());
+        fs::write(dir.join("lib-1.0.jar.sha1"), sha1).unwrap();
+        assert!(verify_cached(&jar).unwrap());
+        fs::write(&jar, b"abd").unwrap();
+        assert!(!verify_cached(&jar).unwrap());
+        remove_cached(&jar).unwrap();
+        assert!(!jar.exists() && !dir.join("lib-1.0.jar.sha1").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn interpolates_properties() {
+        let properties = [
+            ("kotlin.version", "${kotlin.major}.20"),
+            ("kotlin.major", "1.8"),
+            ("loop", "${loop}"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        assert_eq!(
+            interpolate("[${kotlin.version}]", &properties).as_deref(),
+            Ok("[1.8.20]")
+        );
+        assert_eq!(
+            interpolate("${env.HOME}", &properties),
+            Err("env.HOME".to_string())
+        );
+        assert_eq!(interpolate("${loop}", &properties), Err("loop".to_string()));
+    }
+
+    /// Places the POM of `coordinate` in the cache at `dir`, as if it was downloaded
+    fn cache_pom(dir: &Path, coordinate: &Coordinate, xml: &str) {
+        let dir = dir.join(coordinate.dir());
+        let file = dir.join(coordinate.file_name("pom"));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&file, xml).unwrap();
+        fs::write(
+            format!("{}.sha1", file.display()),
+            Checksum::Sha1.hex_digest(xml.as_bytes()),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn inherits_from_parent_and_bom() {
+        let dir = std::env::temp_dir().join(format!("ndk-build-pom-{}", std::process::id()));
+        let parent = Coordinate::new("com.example", "parent", "2.0");
+        let bom = Coordinate::new("com.example", "bom", "3.0");
+        let library = Coordinate::new("com.example", "library", "1.0");
+        cache_pom(
+            &dir,
+            &parent,
+            r#"<project>
+  <properties><annotation.version>1.6.0</annotation.version></properties>
+  <dependencyManagement><dependencies>
+    <dependency><groupId>com.example</groupId><artifactId>core</artifactId><version>${project.version}</version></dependency>
+    <dependency><groupId>com.example</groupId><artifactId>bom</artifactId><version>3.0</version><scope>import</scope><type>pom</type></dependency>
+    <dependency><groupId>junit</groupId><artifactId>junit</artifactId><version>4.13</version><scope>test</scope></dependency>
+  </dependencies></dependencyManagement>
+  <dependencies>
+    <dependency><groupId>androidx.annotation</groupId><artifactId>annotation</artifactId><version>${annotation.version}</version></dependency>
+  </dependencies>
+</project>"#,
+        );
+        cache_pom(
+            &dir,
+            &bom,
+            r#"<project>
+  <dependencyManagement><dependencies>
+    <dependency><groupId>com.example</groupId><artifactId>core</artifactId><version>0.1</version></dependency>
+    <dependency><groupId>com.example</groupId><artifactId>extra</artifactId><version>${project.version}</version></dependency>
+  </dependencies></dependencyManagement>
+</project>"#,
+        );
+        cache_pom(
+            &dir,
+            &library,
+            r#"<project>
+  <parent><groupId>com.example</groupId><artifactId>parent</artifactId><version>2.0</version></parent>
+  <artifactId>library</artifactId>
+  <version>1.0</version>
+  <packaging>aar</packaging>
+  <properties><annotation.version>1.7.0</annotation.version></properties>
+  <dependencies>
+    <dependency><groupId>com.example</groupId><artifactId>core</artifactId></dependency>
+    <dependency><groupId>com.example</groupId><artifactId>extra</artifactId></dependency>
+    <dependency><groupId>junit</groupId><artifactId>junit</artifactId></dependency>
+  </dependencies>
+</project>"#,
+        );
+
+        let repository = Repository::new(dir.clone());
+        let pom = repository.pom(&library).unwrap();
+        assert_eq!(pom.packaging.as_deref(), Some("aar"));
+        let dependencies = pom
+            .dependencies
+            .dependency
+            .iter()
+            .map(|d| format!("{}:{}", d.artifact_id, d.version.as_deref().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(dependencies, ["core:1.0", "extra:3.0", "annotation:1.7.0"]);
+
+        let broken = Coordinate::new("com.example", "broken", "1.0");
+        cache_pom(
+            &dir,
+            &broken,
+            r#"<project><dependencies>
+    <dependency><groupId>com.example</groupId><artifactId>core</artifactId><version>${core.version}</version></dependency>
+</dependencies></project>"#,
+        );
+        assert!(matches!(
+            repository.pom(&broken),
+            Err(NdkError::UnsupportedPom(pom, feature))
+                if pom == "com.example:broken:1.0" && feature == "unresolved property `${core.version}`"
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn parse_pom() {
+        let pom: Pom = quick_xml::de::from_str(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<project xmlns="http://maven.apache.org/POM/4.0.0">
+  <modelVersion>4.0.0</modelVersion>
+  <groupId>androidx.games</groupId>
+  <artifactId>games-activity</artifactId>
+  <version>2.0.2</version>
+  <packaging>aar</packaging>
+  <dependencies>
+    <dependency>
+      <groupId>androidx.appcompat</groupId>
+      <artifactId>appcompat</artifactId>
+      <version>1.6.1</version>
+      <scope>compile</scope>
+    </dependency>
+    <dependency>
+      <groupId>junit</groupId>
+      <artifactId>junit</artifactId>
+      <version>4.13</version>
+      <scope>test</scope>
+    </dependency>
+  </dependencies>
+</project>"#,
+        )
+        .unwrap();
+        assert_eq!(pom.packaging.as_deref(), Some("aar"));
+        let runtime = pom
+            .dependencies
+            .dependency
+            .iter()
+            .filter(|d| d.is_runtime())
+            .map(|d| d.artifact_id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(runtime, ["appcompat"]);
+    }
+}
@@ -392,6 +392,26 @@ impl Ndk {
         Err(NdkError::CmdNotFound("keytool".to_string()))
     }
 
+    pub fn javac(&self) -> Result<Command, NdkError> {
+        if let Ok(java) = std::env::var("JAVA_HOME") {
+            let javac = PathBuf::from(java).join("bin").join(bin!("javac"));
+            if javac.exists() {
+                return Ok(Command::new(javac));
+            }
+        }
+        if let Ok(javac) = which::which(bin!("javac")) {
+            return Ok(Command::new(javac));
+        }
+        Err(NdkError::CmdNotFound("javac".to_string()))
+    }
+
+    pub fn kotlinc(&self) -> Result<Command, NdkError> {
+        if let Ok(kotlinc) = which::which(bat!("kotlinc")) {
+            return Ok(Command::new(kotlinc));
+        }
+        Err(NdkError::CmdNotFound("kotlinc".to_string()))
+    }
+
     pub fn debug_key(&self) -> Result<Key, NdkError> {
         let path = self.android_user_home()?.join("debug.keystore");
         let password = DEFAULT_DEV_KEYSTORE_PASSWORD.to_owned();
@@ -421,7 +441,11 @@ impl Ndk {
                 return Err(NdkError::CmdFailed(Box::new(keytool)));
             }
         }
-        Ok(Key { path, password })
+        Ok(Key {
+            path,
+            password,
+            certificate: None,
+        })
     }
 
     pub fn sysroot_lib_dir(&self, target: Target) -> Result<PathBuf, NdkError> {
@@ -480,6 +504,28 @@ impl Ndk {
         Target::from_android_abi(abi.trim())
     }
 
+    /// Lists all supported ABIs of the device, most preferred first, ignoring ABIs
+    /// that are not a [`Target`]
+    pub fn detect_abis(&self, device_serial: Option<&str>) -> Result<Vec<Target>, NdkError> {
+        let mut adb = self.adb(device_serial)?;
+
+        let stdout = adb
+            .arg("shell")
+            .arg("getprop")
+            .arg("ro.product.cpu.abilist")
+            .output()?
+            .stdout;
+        let abis = std::str::from_utf8(&stdout).or(Err(NdkError::UnsupportedTarget))?;
+        let targets = parse_abilist(abis);
+
+        // `ro.product.cpu.abilist` is only available since Android 5.0
+        if targets.is_empty() {
+            Ok(vec![self.detect_abi(device_serial)?])
+        } else {
+            Ok(targets)
+        }
+    }
+
     pub fn adb(&self, device_serial: Option<&str>) -> Result<Command, NdkError> {
         let mut adb = Command::new(self.adb_path()?);
 
@@ -491,9 +537,23 @@ impl Ndk {
     }
 }
 
+/// Parses the comma-separated `ro.product.cpu.abilist` property, keeping the order
+/// and skipping ABIs that are not a [`Target`]
+fn parse_abilist(abis: &str) -> Vec<Target> {
+    abis.split(',')
+        .filter_map(|abi| Target::from_android_abi(abi.trim()).ok())
+        .collect()
+}
+
+/// Key used to sign APKs, see [`crate::keystore::SigningKey::load`]
 pub struct Key {
+    /// Path to a PKCS#12 or JKS keystore, or to a PEM private key
     pub path: PathBuf,
+    /// Password of the keystore and the key within it
     pub password: String,
+    /// Path to a PEM or DER certificate (chain), when [`Key::path`] is a PEM private key
+    /// without certificates
+    pub certificate: Option<PathBuf>,
 }
 
 #[cfg(test)]
@@ -507,4 +567,23 @@ mod tests {
         assert_eq!(ndk.build_tools_version(), "29.0.2");
         assert_eq!(ndk.platforms(), &[29, 28]);
     }
+
+    #[test]
+    fn parses_abilist() {
+        assert_eq!(
+            parse_abilist("arm64-v8a,armeabi-v7a,armeabi\n"),
+            [Target::Arm64V8a, Target::ArmV7a]
+        );
+        assert_eq!(
+            parse_abilist("x86_64,x86,arm64-v8a,armeabi-v7a,armeabi\r\n"),
+            [
+                Target::X86_64,
+                Target::X86,
+                Target::Arm64V8a,
+                Target::ArmV7a
+            ]
+        );
+        // Android 4.4 and older don't have the property
+        assert_eq!(parse_abilist("\n"), []);
+    }
 }
@@ -1,12 +1,17 @@
-use crate::apk::UnalignedApk;
+use crate::apk::{MissingLibs, UnalignedApk};
+use crate::elf::Elf;
 use crate::error::NdkError;
 use crate::target::Target;
 use std::collections::HashSet;
-use std::io::BufRead;
+use std::fmt::Write;
 use std::path::{Path, PathBuf};
-use std::process::Command;
 
 impl<'a> UnalignedApk<'a> {
+    /// Adds `lib` and the libraries it depends on from `search_paths` and the NDK,
+    /// handling dependencies that are not found and symbols that the platform stubs of
+    /// `min_sdk_version` don't define according to [`ApkConfig::missing_libs`]
+    ///
+    /// [`ApkConfig::missing_libs`]: crate::apk::ApkConfig::missing_libs
     pub fn add_lib_recursively(
         &mut self,
         lib: &Path,
@@ -21,7 +26,8 @@ impl<'a> UnalignedApk<'a> {
             .sdk
             .min_sdk_version
             .unwrap_or(default_min_sdk);
-        let readelf_path = ndk.toolchain_bin("readelf", target)?;
+        let missing_libs = self.config().missing_libs;
+        let allowed_missing_libs = self.config().allowed_missing_libs.clone();
 
         let android_search_paths = [
             &*ndk.sysroot_lib_dir(target)?,
@@ -37,10 +43,16 @@ impl<'a> UnalignedApk<'a> {
             }
         }
 
-        let mut artifacts = vec![lib.to_path_buf()];
-        while let Some(artifact) = artifacts.pop() {
+        let mut libs = Vec::new();
+        let mut unresolved = HashSet::new();
+        // Libraries to add, with the chain of libraries that depend on them
+        let mut artifacts = vec![(lib.to_path_buf(), Vec::new())];
+        while let Some((artifact, mut chain)) = artifacts.pop() {
             self.add_lib(&artifact, target)?;
-            for need in list_needed_libs(&readelf_path, &artifact)? {
+            let elf = Elf::read(&artifact)?;
+            let name = artifact.file_name().unwrap().to_str().unwrap().to_string();
+            chain.push(name.clone());
+            for need in elf.needed.iter().cloned() {
                 // c++_shared is available in the NDK but not on-device.
                 // Must be bundled with the apk if used:
                 // https://developer.android.com/ndk/guides/cpp-support#libc
@@ -54,39 +66,130 @@ impl<'a> UnalignedApk<'a> {
 
                 if let Some(path) = find_library_path(search_paths, &need)? {
                     if provided.insert(path.file_name().unwrap().to_str().unwrap().to_string()) {
-                        artifacts.push(path);
+                        artifacts.push((path, chain.clone()));
+                    }
+                    continue;
+                }
+                let allowed = allowed_missing_libs
+                    .iter()
+                    .any(|pattern| matches_wildcard(pattern, &need));
+                if !allowed {
+                    let error = NdkError::MissingSharedLibrary {
+                        library: need.clone(),
+                        chain: chain.clone(),
+                    };
+                    match missing_libs {
+                        MissingLibs::Error => return Err(error),
+                        MissingLibs::Warn => eprintln!("Warning: {error}"),
+                        MissingLibs::Ignore => {}
                     }
-                } else {
-                    eprintln!("Shared library \"{need}\" not found.");
                 }
+                unresolved.insert(need);
+            }
+            libs.push((name, elf));
+        }
+
+        // Only check the symbols against the platform stubs when a library, the manifest
+        // or the packaging options changed since the previous build
+        let abi = target.android_abi();
+        let unchanged = self.fingerprint.link == self.previous.link
+            && libs.iter().all(|(name, _)| {
+                let lib_path_unix = format!("lib/{abi}/{name}");
+                self.fingerprint
+                    .lib_unchanged(&self.previous, &lib_path_unix)
+            });
+        if unchanged || missing_libs == MissingLibs::Ignore {
+            return Ok(());
+        }
+
+        // Load the platform stubs of `min_sdk_version` that the libraries link against
+        let mut platform = Vec::new();
+        let mut needed = libs
+            .iter()
+            .flat_map(|(_, elf)| elf.needed.iter().cloned())
+            .collect::<Vec<_>>();
+        let mut visited = libs
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<HashSet<_>>();
+        while let Some(need) = needed.pop() {
+            if !visited.insert(need.clone()) {
+                continue;
+            }
+            if let Some(path) = find_library_path(&android_search_paths, &need)? {
+                let stub = Elf::read(&path)?;
+                needed.extend(stub.needed.iter().cloned());
+                platform.push(stub);
             }
         }
 
-        Ok(())
+        let unavailable = unavailable_symbols(&libs, &platform, &unresolved);
+        if unavailable.is_empty() {
+            return Ok(());
+        }
+        let mut symbols = String::new();
+        for (lib, lib_symbols) in unavailable {
+            write!(symbols, "\n  {lib}: {}", lib_symbols.join(", ")).unwrap();
+        }
+        let error = NdkError::UnavailableSymbols(min_sdk_version, symbols);
+        match missing_libs {
+            MissingLibs::Error => Err(error),
+            MissingLibs::Warn => {
+                eprintln!("Warning: {error}");
+                Ok(())
+            }
+            MissingLibs::Ignore => Ok(()),
+        }
     }
 }
 
-/// List all linked shared libraries
-fn list_needed_libs(readelf_path: &Path, library_path: &Path) -> Result<HashSet<String>, NdkError> {
-    let mut readelf = Command::new(readelf_path);
-    let output = readelf.arg("-d").arg(library_path).output()?;
-    if !output.status.success() {
-        return Err(NdkError::CmdFailed(Box::new(readelf)));
-    }
-    let mut needed = HashSet::new();
-    for line in output.stdout.lines() {
-        let line = line?;
-        if line.contains("(NEEDED)") {
-            let lib = line
-                .split("Shared library: [")
-                .last()
-                .and_then(|line| line.split(']').next());
-            if let Some(lib) = lib {
-                needed.insert(lib.to_string());
-            }
+/// Returns the undefined symbols of each of the `libs` that are neither defined by
+/// another of the `libs` nor by the `platform` libraries, which makes the dynamic
+/// linker fail to load them. Libraries that depend on one of the `unresolved` libraries
+/// are skipped, as the symbols may be defined there.
+fn unavailable_symbols<'a>(
+    libs: &'a [(String, Elf)],
+    platform: &[Elf],
+    unresolved: &HashSet<String>,
+) -> Vec<(&'a str, Vec<&'a str>)> {
+    let available = libs
+        .iter()
+        .map(|(_, elf)| elf)
+        .chain(platform)
+        .flat_map(|elf| elf.defined_symbols.iter().map(String::as_str))
+        .collect::<HashSet<_>>();
+    libs.iter()
+        .filter(|(_, elf)| !elf.needed.iter().any(|need| unresolved.contains(need)))
+        .filter_map(|(name, elf)| {
+            let mut symbols = elf
+                .undefined_symbols
+                .iter()
+                .map(String::as_str)
+                .filter(|symbol| !available.contains(symbol))
+                .collect::<Vec<_>>();
+            symbols.sort_unstable();
+            (!symbols.is_empty()).then_some((name.as_str(), symbols))
+        })
+        .collect()
+}
+
+/// Matches `name` against `pattern`, in which `*` matches any sequence of characters
+fn matches_wildcard(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = name.strip_prefix(parts.next().unwrap()) else {
+        return false;
+    };
+    let parts = parts.collect::<Vec<_>>();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
         }
     }
-    Ok(needed)
+    rest.ends_with(last)
 }
 
 /// List shared libraries
@@ -119,3 +222,61 @@ fn find_library_path<S: AsRef<Path>>(
     }
     Ok(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elf(needed: &[&str], undefined: &[&str], defined: &[&str]) -> Elf {
+        let strings = |strings: &[&str]| strings.iter().map(|s| s.to_string()).collect();
+        Elf {
+            needed: strings(needed),
+            undefined_symbols: strings(undefined),
+            defined_symbols: strings(defined),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn unavailable_symbols_of_min_sdk() {
+        let libs = [
+            (
+                "libmain.so".to_string(),
+                elf(
+                    &["libc.so", "libc++_shared.so"],
+                    &[
+                        "malloc",
+                        "getrandom",
+                        "__cxa_throw",
+                        "AHardwareBuffer_allocate",
+                    ],
+                    &["ANativeActivity_onCreate"],
+                ),
+            ),
+            (
+                "libc++_shared.so".to_string(),
+                elf(&["libc.so"], &["malloc"], &["__cxa_throw"]),
+            ),
+            (
+                "libvendor.so".to_string(),
+                elf(&["libc.so", "libmissing.so"], &["vendor_init"], &[]),
+            ),
+        ];
+        let platform = [elf(&[], &[], &["malloc", "free"])];
+        let unresolved = ["libmissing.so".to_string()].iter().cloned().collect();
+        assert_eq!(
+            unavailable_symbols(&libs, &platform, &unresolved),
+            [("libmain.so", vec!["AHardwareBuffer_allocate", "getrandom"])]
+        );
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches_wildcard("libvulkan.so", "libvulkan.so"));
+        assert!(!matches_wildcard("libvulkan.so", "libvulkan.so.1"));
+        assert!(matches_wildcard("libvulkan*.so", "libvulkan_adreno.so"));
+        assert!(matches_wildcard("lib*_oem*.so", "libcamera_oem_v2.so"));
+        assert!(!matches_wildcard("lib*_oem*.so", "libcamera.so"));
+        assert!(matches_wildcard("*", "libanything.so"));
+    }
+}
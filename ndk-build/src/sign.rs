@@ -0,0 +1,737 @@
+//! Native implementation of the APK signature schemes: v1 (signed JAR), [v2] and [v3].
+//! App Bundles are signed with the v1 scheme only.
+//!
+//! Only RSA keys are supported, producing `RSASSA-PKCS1-v1_5` signatures over
+//! SHA-256 digests (or SHA-1 for v1 signatures when targeting API levels below 18).
+//!
+//! [v2]: https://source.android.com/docs/security/features/apksigning/v2
+//! [v3]: https://source.android.com/docs/security/features/apksigning/v3
+
+use crate::error::NdkError;
+use crate::keystore::SigningKey;
+use crate::zip::{alignment_for, Compression, DosDateTime, ZipArchive, ZipWriter};
+use base64::Engine;
+use rsa::Pkcs1v15Sign;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+use std::path::Path;
+use x509_cert::der::Encode;
+
+const APK_SIGNATURE_SCHEME_V2_BLOCK_ID: u32 = 0x7109871a;
+const APK_SIGNATURE_SCHEME_V3_BLOCK_ID: u32 = 0xf05368c0;
+const APK_SIGNING_BLOCK_MAGIC: &[u8; 16] = b"APK Sig Block 42";
+/// Additional v2 attribute signaling that the APK is also signed with the v3
+/// scheme, protecting against stripping of the v3 block
+const STRIPPING_PROTECTION_ATTR_ID: u32 = 0xbeeff00d;
+const SIGNATURE_RSA_PKCS1_V1_5_WITH_SHA256: u32 = 0x0103;
+const CONTENT_DIGEST_CHUNK_SIZE: usize = 1 << 20;
+
+/// Android P, the first release to verify v3 signatures
+const V3_MIN_SDK_VERSION: u32 = 28;
+const V3_MAX_SDK_VERSION: u32 = i32::MAX as u32;
+/// Android N, the first release to verify v2 signatures. v1 signatures are only
+/// required when the APK supports older releases.
+const V2_MIN_SDK_VERSION: u32 = 24;
+/// Android 4.3, the first release to support SHA-256 digests in v1 signatures
+const V1_SHA256_MIN_SDK_VERSION: u32 = 18;
+
+// Pre-encoded DER object identifiers, including tag and length
+const OID_SIGNED_DATA: &[u8] = &[
+    0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02,
+];
+const OID_DATA: &[u8] = &[
+    0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x01,
+];
+const OID_SHA1: &[u8] = &[0x06, 0x05, 0x2b, 0x0e, 0x03, 0x02, 0x1a];
+const OID_SHA256: &[u8] = &[
+    0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01,
+];
+const OID_RSA_ENCRYPTION: &[u8] = &[
+    0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01,
+];
+const DER_NULL: &[u8] = &[0x05, 0x00];
+const DER_INTEGER_ONE: &[u8] = &[0x02, 0x01, 0x01];
+
+/// Signs the APK at `apk` in place with the v2 and v3 schemes, and additionally
+/// with the v1 scheme when `min_sdk_version` is below 24.
+pub fn sign_apk(apk: &Path, key: &SigningKey, min_sdk_version: u32) -> Result<(), NdkError> {
+    let mut data = std::fs::read(apk).map_err(|e| NdkError::IoPathError(apk.to_owned(), e))?;
+    if min_sdk_version < V2_MIN_SDK_VERSION {
+        data = sign_jar(&data, key, min_sdk_version, true)?;
+    }
+    let data = sign_v2_v3(&data, key)?;
+    std::fs::write(apk, data).map_err(|e| NdkError::IoPathError(apk.to_owned(), e))
+}
+
+/// Signs the Android App Bundle at `aab` in place with a v1 JAR signature, the
+/// only scheme accepted for bundles uploaded to Google Play.
+pub fn sign_bundle(aab: &Path, key: &SigningKey) -> Result<(), NdkError> {
+    let data = std::fs::read(aab).map_err(|e| NdkError::IoPathError(aab.to_owned(), e))?;
+    let data = sign_jar(&data, key, V1_SHA256_MIN_SDK_VERSION, false)?;
+    std::fs::write(aab, data).map_err(|e| NdkError::IoPathError(aab.to_owned(), e))
+}
+
+#[derive(Clone, Copy)]
+enum JarDigest {
+    Sha1,
+    Sha256,
+}
+
+impl JarDigest {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Sha1 => "SHA1",
+            Self::Sha256 => "SHA-256",
+        }
+    }
+
+    fn oid(self) -> &'static [u8] {
+        match self {
+            Self::Sha1 => OID_SHA1,
+            Self::Sha256 => OID_SHA256,
+        }
+    }
+
+    fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Sha1 => Sha1::digest(data).to_vec(),
+            Self::Sha256 => Sha256::digest(data).to_vec(),
+        }
+    }
+
+    fn sign(self, key: &SigningKey, data: &[u8]) -> Result<Vec<u8>, NdkError> {
+        let (padding, digest) = match self {
+            Self::Sha1 => (Pkcs1v15Sign::new::<Sha1>(), self.digest(data)),
+            Self::Sha256 => (Pkcs1v15Sign::new::<Sha256>(), self.digest(data)),
+        };
+        key.private_key
+            .sign(padding, &digest)
+            .map_err(|e| NdkError::Signing(e.to_string()))
+    }
+}
+
+/// Returns `true` for files written by (v1) JAR signing
+fn is_jar_signature_file(name: &str) -> bool {
+    match name.strip_prefix("META-INF/") {
+        Some("MANIFEST.MF") => true,
+        Some(file) if !file.contains('/') => [".SF", ".RSA", ".DSA", ".EC"]
+            .iter()
+            .any(|ext| file.ends_with(ext)),
+        _ => false,
+    }
+}
+
+/// Appends a `name: value` manifest attribute, wrapping lines at 72 bytes as
+/// required by the JAR specification
+fn write_attribute(out: &mut Vec<u8>, name: &str, value: &str) {
+    let line = format!("{name}: {value}");
+    let mut bytes = line.as_bytes();
+    let mut limit = 72;
+    while bytes.len() > limit {
+        // Never split multi-byte UTF-8 sequences
+        let mut split = limit;
+        while split > 0 && (bytes[split] & 0xc0) == 0x80 {
+            split -= 1;
+        }
+        out.extend_from_slice(&bytes[..split]);
+        out.extend_from_slice(b"\r\n ");
+        bytes = &bytes[split..];
+        limit = 71;
+    }
+    out.extend_from_slice(bytes);
+    out.extend_from_slice(b"\r\n");
+}
+
+/// Signs the zip archive in `data` with a v1 JAR signature, returning the new archive.
+///
+/// `apk_signed` marks the signature as accompanied by v2 and v3 signatures, so that
+/// Android versions supporting those reject APKs that had them stripped.
+pub(crate) fn sign_jar(
+    data: &[u8],
+    key: &SigningKey,
+    min_sdk_version: u32,
+    apk_signed: bool,
+) -> Result<Vec<u8>, NdkError> {
+    let archive = ZipArchive::parse(data)?;
+    let digest = if min_sdk_version < V1_SHA256_MIN_SDK_VERSION {
+        JarDigest::Sha1
+    } else {
+        JarDigest::Sha256
+    };
+    let digest_attribute = format!("{}-Digest", digest.name());
+    let base64 = base64::engine::general_purpose::STANDARD;
+
+    let mut entries = archive
+        .entries()
+        .iter()
+        .filter(|e| !e.name.ends_with('/') && !is_jar_signature_file(&e.name))
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut manifest = Vec::new();
+    write_attribute(&mut manifest, "Manifest-Version", "1.0");
+    write_attribute(&mut manifest, "Created-By", "1.0 (Android)");
+    manifest.extend_from_slice(b"\r\n");
+
+    let mut sections = Vec::new();
+    for entry in &entries {
+        let mut section = Vec::new();
+        write_attribute(&mut section, "Name", &entry.name);
+        let entry_digest = digest.digest(&archive.read(entry)?);
+        write_attribute(
+            &mut section,
+            &digest_attribute,
+            &base64.encode(entry_digest),
+        );
+        section.extend_from_slice(b"\r\n");
+
+        write_attribute(&mut sections, "Name", &entry.name);
+        let section_digest = digest.digest(&section);
+        write_attribute(
+            &mut sections,
+            &digest_attribute,
+            &base64.encode(section_digest),
+        );
+        sections.extend_from_slice(b"\r\n");
+
+        manifest.extend_from_slice(&section);
+    }
+
+    let mut signature_file = Vec::new();
+    write_attribute(&mut signature_file, "Signature-Version", "1.0");
+    write_attribute(&mut signature_file, "Created-By", "1.0 (Android)");
+    write_attribute(
+        &mut signature_file,
+        &format!("{}-Manifest", digest_attribute),
+        &base64.encode(digest.digest(&manifest)),
+    );
+    if apk_signed {
+        write_attribute(&mut signature_file, "X-Android-APK-Signed", "2, 3");
+    }
+    signature_file.extend_from_slice(b"\r\n");
+    signature_file.extend_from_slice(&sections);
+
+    let signature_block = pkcs7_signed_data(key, digest, &signature_file)?;
+
+    let mut zip = ZipWriter::new(Vec::with_capacity(data.len()));
+    for (name, contents) in [
+        ("META-INF/MANIFEST.MF", &manifest),
+        ("META-INF/CERT.SF", &signature_file),
+        ("META-INF/CERT.RSA", &signature_block),
+    ] {
+        zip.add_entry(
+            name,
+            contents,
+            Compression::Deflated,
+            DosDateTime::EPOCH,
+            alignment_for(name),
+        )?;
+    }
+    for entry in archive.entries() {
+        if !is_jar_signature_file(&entry.name) {
+            zip.copy_entry(entry, archive.raw_data(entry), alignment_for(&entry.name))?;
+        }
+    }
+    zip.finish()
+}
+
+fn der_length(out: &mut Vec<u8>, len: usize) {
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&b| b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+}
+
+fn der(tag: u8, parts: &[&[u8]]) -> Vec<u8> {
+    let len = parts.iter().map(|p| p.len()).sum();
+    let mut out = vec![tag];
+    der_length(&mut out, len);
+    for part in parts {
+        out.extend_from_slice(part);
+    }
+    out
+}
+
+fn der_sequence(parts: &[&[u8]]) -> Vec<u8> {
+    der(0x30, parts)
+}
+
+/// Creates a detached PKCS#7 `SignedData` structure signing `content`, as stored in
+/// the `META-INF/*.RSA` file of a signed JAR
+fn pkcs7_signed_data(
+    key: &SigningKey,
+    digest: JarDigest,
+    content: &[u8],
+) -> Result<Vec<u8>, NdkError> {
+    let encode_err = |e: x509_cert::der::Error| NdkError::InvalidCertificate(e.to_string());
+    let cert = key.leaf_certificate()?;
+    let issuer = cert.tbs_certificate.issuer.to_der().map_err(encode_err)?;
+    let serial = cert
+        .tbs_certificate
+        .serial_number
+        .to_der()
+        .map_err(encode_err)?;
+
+    let digest_algorithm = der_sequence(&[digest.oid(), DER_NULL]);
+    let signature = digest.sign(key, content)?;
+    let signer_info = der_sequence(&[
+        DER_INTEGER_ONE,
+        &der_sequence(&[&issuer, &serial]),
+        &digest_algorithm,
+        &der_sequence(&[OID_RSA_ENCRYPTION, DER_NULL]),
+        &der(0x04, &[&signature]),
+    ]);
+
+    let certificates = key
+        .certificates
+        .iter()
+        .map(Vec::as_slice)
+        .collect::<Vec<_>>();
+    let signed_data = der_sequence(&[
+        DER_INTEGER_ONE,
+        &der(0x31, &[&digest_algorithm]),
+        &der_sequence(&[OID_DATA]),
+        &der(0xa0, &certificates),
+        &der(0x31, &[&signer_info]),
+    ]);
+
+    Ok(der_sequence(&[
+        OID_SIGNED_DATA,
+        &der(0xa0, &[&signed_data]),
+    ]))
+}
+
+fn length_prefixed(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+fn length_prefixed_sequence<'a>(items: impl IntoIterator<Item = &'a [u8]>) -> Vec<u8> {
+    let mut sequence = Vec::new();
+    for item in items {
+        length_prefixed(&mut sequence, item);
+    }
+    let mut out = Vec::with_capacity(sequence.len() + 4);
+    length_prefixed(&mut out, &sequence);
+    out
+}
+
+/// Computes the `CONTENT_DIGEST_CHUNKED_SHA256` digest over the given sections
+fn chunked_sha256(sections: &[&[u8]]) -> Vec<u8> {
+    let mut chunk_count = 0u32;
+    let mut chunk_digests = Vec::new();
+    for section in sections {
+        for chunk in section.chunks(CONTENT_DIGEST_CHUNK_SIZE) {
+            let mut hasher = Sha256::new();
+            hasher.update([0xa5]);
+            hasher.update((chunk.len() as u32).to_le_bytes());
+            hasher.update(chunk);
+            chunk_digests.extend_from_slice(&hasher.finalize());
+            chunk_count += 1;
+        }
+    }
+    let mut hasher = Sha256::new();
+    hasher.update([0x5a]);
+    hasher.update(chunk_count.to_le_bytes());
+    hasher.update(&chunk_digests);
+    hasher.finalize().to_vec()
+}
+
+/// Serializes a signer for the v2 scheme, or for the v3 scheme when `v3` is `true`
+fn signer(key: &SigningKey, digest: &[u8], v3: bool) -> Result<Vec<u8>, NdkError> {
+    let mut digest_entry = SIGNATURE_RSA_PKCS1_V1_5_WITH_SHA256.to_le_bytes().to_vec();
+    length_prefixed(&mut digest_entry, digest);
+
+    let mut signed_data = length_prefixed_sequence([digest_entry.as_slice()]);
+    signed_data.extend(length_prefixed_sequence(
+        key.certificates.iter().map(Vec::as_slice),
+    ));
+    if v3 {
+        signed_data.extend_from_slice(&V3_MIN_SDK_VERSION.to_le_bytes());
+        signed_data.extend_from_slice(&V3_MAX_SDK_VERSION.to_le_bytes());
+        signed_data.extend(length_prefixed_sequence([]));
+    } else {
+        let mut stripping_protection = STRIPPING_PROTECTION_ATTR_ID.to_le_bytes().to_vec();
+        stripping_protection.extend_from_slice(&3u32.to_le_bytes());
+        signed_data.extend(length_prefixed_sequence([stripping_protection.as_slice()]));
+    }
+
+    let mut signature = SIGNATURE_RSA_PKCS1_V1_5_WITH_SHA256.to_le_bytes().to_vec();
+    length_prefixed(&mut signature, &JarDigest::Sha256.sign(key, &signed_data)?);
+
+    let mut signer = Vec::new();
+    length_prefixed(&mut signer, &signed_data);
+    if v3 {
+        signer.extend_from_slice(&V3_MIN_SDK_VERSION.to_le_bytes());
+        signer.extend_from_slice(&V3_MAX_SDK_VERSION.to_le_bytes());
+    }
+    signer.extend(length_prefixed_sequence([signature.as_slice()]));
+    length_prefixed(&mut signer, &key.leaf_public_key_der()?);
+
+    Ok(length_prefixed_sequence([signer.as_slice()]))
+}
+
+/// Inserts an APK Signing Block with v2 and v3 signatures in front of the central
+/// directory of the zip archive in `data`
+fn sign_v2_v3(data: &[u8], key: &SigningKey) -> Result<Vec<u8>, NdkError> {
+    let archive = ZipArchive::parse(data)?;
+    let cd_offset = archive.central_directory_offset();
+    let eocd_offset = archive.end_of_central_directory_offset();
+    if data[..cd_offset].ends_with(APK_SIGNING_BLOCK_MAGIC) {
+        return Err(NdkError::Signing("APK is already signed".to_string()));
+    }
+
+    // The central directory offset in the end of central directory record is
+    // digested as the offset of the signing block, which is the same as the
+    // offset of the central directory in the unsigned archive
+    let digest = chunked_sha256(&[
+        &data[..cd_offset],
+        &data[cd_offset..eocd_offset],
+        &data[eocd_offset..],
+    ]);
+
+    let mut pairs = Vec::new();
+    for (id, value) in [
+        (
+            APK_SIGNATURE_SCHEME_V2_BLOCK_ID,
+            signer(key, &digest, false)?,
+        ),
+        (
+            APK_SIGNATURE_SCHEME_V3_BLOCK_ID,
+            signer(key, &digest, true)?,
+        ),
+    ] {
+        pairs.extend_from_slice(&(value.len() as u64 + 4).to_le_bytes());
+        pairs.extend_from_slice(&id.to_le_bytes());
+        pairs.extend_from_slice(&value);
+    }
+    let block_size = (pairs.len() + 8 + APK_SIGNING_BLOCK_MAGIC.len()) as u64;
+    let mut block = block_size.to_le_bytes().to_vec();
+    block.extend_from_slice(&pairs);
+    block.extend_from_slice(&block_size.to_le_bytes());
+    block.extend_from_slice(APK_SIGNING_BLOCK_MAGIC);
+
+    let mut signed = Vec::with_capacity(data.len() + block.len());
+    signed.extend_from_slice(&data[..cd_offset]);
+    signed.extend_from_slice(&block);
+    signed.extend_from_slice(&data[cd_offset..]);
+
+    let new_cd_offset = u32::try_from(cd_offset + block.len())
+        .map_err(|_| NdkError::InvalidZip("archive exceeds 4 GiB".to_string()))?;
+    let cd_offset_field = eocd_offset + block.len() + 16;
+    signed[cd_offset_field..cd_offset_field + 4].copy_from_slice(&new_cd_offset.to_le_bytes());
+    Ok(signed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keystore::tests::test_key;
+    use rsa::pkcs8::DecodePublicKey;
+    use rsa::RsaPublicKey;
+    use std::convert::TryInto;
+    use std::path::PathBuf;
+
+    /// Splits a `u32` length-prefixed item off the front of `data`
+    fn prefixed<'a>(data: &mut &'a [u8]) -> &'a [u8] {
+        let len = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+        let (item, rest) = data[4..].split_at(len);
+        *data = rest;
+        item
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn unsigned_apk() -> Vec<u8> {
+        let mut zip = ZipWriter::new(Vec::new());
+        for (name, data, compression) in [
+            (
+                "AndroidManifest.xml",
+                &b"<manifest/>"[..],
+                Compression::Deflated,
+            ),
+            ("classes.dex", b"dex\n035\0", Compression::Deflated),
+            ("lib/arm64-v8a/libmain.so", b"\x7fELF", Compression::Stored),
+        ] {
+            zip.add_entry(
+                name,
+                data,
+                compression,
+                DosDateTime::EPOCH,
+                alignment_for(name),
+            )
+            .unwrap();
+        }
+        zip.finish().unwrap()
+    }
+
+    /// Checks the v2 or v3 signer in `value` against the `digest` of the APK contents,
+    /// returning its additional attributes
+    fn verify_signer<'a>(
+        mut value: &'a [u8],
+        key: &SigningKey,
+        digest: &[u8],
+        v3: bool,
+    ) -> Vec<(u32, &'a [u8])> {
+        let mut signers = prefixed(&mut value);
+        let mut signer = prefixed(&mut signers);
+        assert!(signers.is_empty() && value.is_empty());
+
+        let signed_data = prefixed(&mut signer);
+        if v3 {
+            assert_eq!(u32_at(signer, 0), V3_MIN_SDK_VERSION);
+            assert_eq!(u32_at(signer, 4), V3_MAX_SDK_VERSION);
+            signer = &signer[8..];
+        }
+        let mut signatures = prefixed(&mut signer);
+        let mut signature = prefixed(&mut signatures);
+        let public_key = prefixed(&mut signer);
+        assert_eq!(public_key, key.leaf_public_key_der().unwrap());
+
+        assert_eq!(u32_at(signature, 0), SIGNATURE_RSA_PKCS1_V1_5_WITH_SHA256);
+        signature = &signature[4..];
+        RsaPublicKey::from_public_key_der(public_key)
+            .unwrap()
+            .verify(
+                Pkcs1v15Sign::new::<Sha256>(),
+                &Sha256::digest(signed_data),
+                prefixed(&mut signature),
+            )
+            .unwrap();
+
+        let mut data = signed_data;
+        let mut digests = prefixed(&mut data);
+        let mut digest_entry = prefixed(&mut digests);
+        assert_eq!(
+            u32_at(digest_entry, 0),
+            SIGNATURE_RSA_PKCS1_V1_5_WITH_SHA256
+        );
+        digest_entry = &digest_entry[4..];
+        assert_eq!(prefixed(&mut digest_entry), digest);
+
+        let mut certificates = prefixed(&mut data);
+        assert_eq!(prefixed(&mut certificates), key.certificates[0]);
+        if v3 {
+            data = &data[8..];
+        }
+        let mut attributes = prefixed(&mut data);
+        let mut parsed = Vec::new();
+        while !attributes.is_empty() {
+            let attribute = prefixed(&mut attributes);
+            parsed.push((u32_at(attribute, 0), &attribute[4..]));
+        }
+        parsed
+    }
+
+    /// Checks the output against `apksigner` from the newest build tools in the Android
+    /// SDK at `$ANDROID_HOME` or `$ANDROID_SDK_ROOT`. Run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn apksigner_verifies() {
+        let sdk = std::env::var_os("ANDROID_HOME")
+            .or_else(|| std::env::var_os("ANDROID_SDK_ROOT"))
+            .map(PathBuf::from)
+            .expect("ANDROID_HOME or ANDROID_SDK_ROOT must point to the Android SDK");
+        let apksigner = std::fs::read_dir(sdk.join("build-tools"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path().join(bat!("apksigner")))
+            .filter(|path| path.exists())
+            .max()
+            .expect("no build tools with apksigner in the Android SDK");
+
+        let dir = std::env::temp_dir().join(format!("ndk-build-apksigner-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let key = SigningKey::load(&test_key("keystore.pem", None)).unwrap();
+        // Below API level 24, `sign_apk()` adds a v1 signature as well
+        let min_sdk_version = 21;
+        let apk = dir.join("signed.apk");
+        std::fs::write(&apk, unsigned_apk()).unwrap();
+        sign_apk(&apk, &key, min_sdk_version).unwrap();
+
+        let output = std::process::Command::new(apksigner)
+            .arg("verify")
+            .arg("--verbose")
+            .arg("--min-sdk-version")
+            .arg(min_sdk_version.to_string())
+            .arg(&apk)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            output.status.success(),
+            "{}{}",
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        for scheme in [
+            "v1 scheme (JAR signing)",
+            "v2 scheme (APK Signature Scheme v2)",
+            "v3 scheme (APK Signature Scheme v3)",
+        ] {
+            assert!(
+                stdout.contains(&format!("Verified using {scheme}: true")),
+                "{}",
+                stdout
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn signs_apk() {
+        let key = SigningKey::load(&test_key("keystore.pem", None)).unwrap();
+        let unsigned = unsigned_apk();
+        let jar_signed = sign_jar(&unsigned, &key, V1_SHA256_MIN_SDK_VERSION, true).unwrap();
+        let signed = sign_v2_v3(&jar_signed, &key).unwrap();
+
+        // v1: every digest in `CERT.SF` matches `MANIFEST.MF`, whose digests match the
+        // archive, and `CERT.RSA` signs `CERT.SF`
+        let archive = ZipArchive::parse(&signed).unwrap();
+        let read = |name: &str| {
+            let entry = archive.entries().iter().find(|e| e.name == name).unwrap();
+            archive.read(entry).unwrap()
+        };
+        let base64 = base64::engine::general_purpose::STANDARD;
+        let digest = |data: &[u8]| base64.encode(Sha256::digest(data));
+        let manifest = String::from_utf8(read("META-INF/MANIFEST.MF")).unwrap();
+        let signature_file = String::from_utf8(read("META-INF/CERT.SF")).unwrap();
+        let mut sf_sections = signature_file.split("\r\n\r\n");
+        let sf_header = sf_sections.next().unwrap();
+        assert!(sf_header.contains(&format!(
+            "SHA-256-Digest-Manifest: {}",
+            digest(manifest.as_bytes())
+        )));
+        assert!(sf_header.contains("X-Android-APK-Signed: 2, 3"));
+
+        let mut mf_sections = manifest.split_inclusive("\r\n\r\n").skip(1);
+        for name in [
+            "AndroidManifest.xml",
+            "classes.dex",
+            "lib/arm64-v8a/libmain.so",
+        ] {
+            let mf_section = mf_sections.next().unwrap();
+            assert_eq!(
+                mf_section,
+                format!(
+                    "Name: {name}\r\nSHA-256-Digest: {}\r\n\r\n",
+                    digest(&read(name))
+                )
+            );
+            assert_eq!(
+                sf_sections.next().unwrap(),
+                format!(
+                    "Name: {name}\r\nSHA-256-Digest: {}",
+                    digest(mf_section.as_bytes())
+                )
+            );
+        }
+        assert_eq!(mf_sections.next(), None);
+
+        let signature_block = read("META-INF/CERT.RSA");
+        let signature = &signature_block[signature_block.len() - 256..];
+        key.private_key
+            .to_public_key()
+            .verify(
+                Pkcs1v15Sign::new::<Sha256>(),
+                &Sha256::digest(signature_file.as_bytes()),
+                signature,
+            )
+            .unwrap();
+
+        // v2 and v3: the APK Signing Block sits in front of the central directory, and
+        // both signers sign the chunked digest of everything else
+        let cd_offset = archive.central_directory_offset();
+        let eocd_offset = archive.end_of_central_directory_offset();
+        assert_eq!(&signed[cd_offset - 16..cd_offset], APK_SIGNING_BLOCK_MAGIC);
+        let block_size =
+            u64::from_le_bytes(signed[cd_offset - 24..cd_offset - 16].try_into().unwrap());
+        let block_offset = cd_offset - block_size as usize - 8;
+        assert_eq!(
+            &signed[block_offset..block_offset + 8],
+            block_size.to_le_bytes()
+        );
+
+        let mut eocd = signed[eocd_offset..].to_vec();
+        eocd[16..20].copy_from_slice(&(block_offset as u32).to_le_bytes());
+        let mut chunk_digests = Vec::new();
+        for section in [
+            &signed[..block_offset],
+            &signed[cd_offset..eocd_offset],
+            &eocd,
+        ] {
+            let mut chunk = vec![0xa5];
+            chunk.extend_from_slice(&(section.len() as u32).to_le_bytes());
+            chunk.extend_from_slice(section);
+            chunk_digests.extend_from_slice(&Sha256::digest(&chunk));
+        }
+        let mut top_level = vec![0x5a];
+        top_level.extend_from_slice(&3u32.to_le_bytes());
+        top_level.extend_from_slice(&chunk_digests);
+        let content_digest = Sha256::digest(&top_level);
+        // The unsigned APK digests to the same value
+        assert_eq!(&signed[..block_offset], &jar_signed[..block_offset]);
+
+        let mut pairs = &signed[block_offset + 8..cd_offset - 24];
+        let mut ids = Vec::new();
+        while !pairs.is_empty() {
+            let len = u64::from_le_bytes(pairs[..8].try_into().unwrap()) as usize;
+            let (id, value) = (u32_at(pairs, 8), &pairs[12..8 + len]);
+            pairs = &pairs[8 + len..];
+            ids.push(id);
+
+            let v3 = id == APK_SIGNATURE_SCHEME_V3_BLOCK_ID;
+            let attributes = verify_signer(value, &key, &content_digest, v3);
+            if v3 {
+                assert!(attributes.is_empty());
+            } else {
+                assert_eq!(
+                    attributes,
+                    [(STRIPPING_PROTECTION_ATTR_ID, &3u32.to_le_bytes()[..])]
+                );
+            }
+        }
+        assert_eq!(
+            ids,
+            [
+                APK_SIGNATURE_SCHEME_V2_BLOCK_ID,
+                APK_SIGNATURE_SCHEME_V3_BLOCK_ID
+            ]
+        );
+
+        assert!(matches!(
+            sign_v2_v3(&signed, &key),
+            Err(NdkError::Signing(_))
+        ));
+    }
+
+    #[test]
+    fn manifest_attribute_wrapping() {
+        let mut out = Vec::new();
+        let name = format!("lib/arm64-v8a/{}.so", "a".repeat(80));
+        write_attribute(&mut out, "Name", &name);
+        let out = String::from_utf8(out).unwrap();
+        let lines = out.split("\r\n").collect::<Vec<_>>();
+        assert_eq!(lines[0].len(), 72);
+        assert!(lines[1].starts_with(' '));
+        assert!(lines[1].len() <= 72);
+        assert_eq!(
+            lines[0].to_owned() + &lines[1][1..],
+            format!("Name: {name}")
+        );
+    }
+
+    #[test]
+    fn jar_signature_files() {
+        assert!(is_jar_signature_file("META-INF/MANIFEST.MF"));
+        assert!(is_jar_signature_file("META-INF/CERT.RSA"));
+        assert!(!is_jar_signature_file("META-INF/services/foo.RSA"));
+        assert!(!is_jar_signature_file("lib/x86/libfoo.so"));
+    }
+}
@@ -2,6 +2,7 @@ use crate::error::NdkError;
 use serde::Deserialize;
 
 #[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[repr(u8)]
 pub enum Target {
     #[serde(rename = "armv7-linux-androideabi")]
@@ -15,6 +16,9 @@ pub enum Target {
 }
 
 impl Target {
+    /// All supported targets
+    pub const ALL: [Self; 4] = [Self::ArmV7a, Self::Arm64V8a, Self::X86, Self::X86_64];
+
     /// Identifier used in the NDK to refer to the ABI
     pub fn android_abi(self) -> &'static str {
         match self {
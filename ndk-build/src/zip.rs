@@ -0,0 +1,566 @@
+//! Minimal zip reader and writer for packaging APKs in-process.
+//!
+//! Only the subset of the zip format that Android tooling produces and consumes
+//! is supported: a single disk, no zip64 extensions and no encryption. Entries
+//! are either stored or deflated, and stored entries can be aligned within the
+//! archive the same way `zipalign` does.
+
+use crate::error::NdkError;
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+
+const LOCAL_FILE_HEADER_LEN: usize = 30;
+const CENTRAL_DIRECTORY_HEADER_LEN: usize = 46;
+const END_OF_CENTRAL_DIRECTORY_LEN: usize = 22;
+
+/// Extra field used by `zipalign` and `apksigner` to pad local file headers,
+/// so that the data of stored entries starts at an aligned offset
+const ALIGNMENT_EXTRA_FIELD_ID: u16 = 0xd935;
+const ALIGNMENT_EXTRA_FIELD_LEN: usize = 6;
+
+/// Alignment required by Android for uncompressed entries like `resources.arsc`
+pub const DEFAULT_ALIGNMENT: u16 = 4;
+/// Alignment required to `mmap` uncompressed `.so` files directly from the APK on
+/// devices with 16 KiB pages, which is also compatible with 4 KiB page devices
+pub const PAGE_ALIGNMENT: u16 = 16384;
+
+/// Returns the alignment for stored entries named `name`: [`PAGE_ALIGNMENT`] for
+/// native libraries and [`DEFAULT_ALIGNMENT`] for everything else
+pub fn alignment_for(name: &str) -> u16 {
+    if name.ends_with(".so") {
+        PAGE_ALIGNMENT
+    } else {
+        DEFAULT_ALIGNMENT
+    }
+}
+
+/// Compression method of a zip entry
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    Stored,
+    Deflated,
+}
+
+impl Compression {
+    fn method(self) -> u16 {
+        match self {
+            Self::Stored => 0,
+            Self::Deflated => 8,
+        }
+    }
+
+    fn from_method(method: u16) -> Result<Self, NdkError> {
+        match method {
+            0 => Ok(Self::Stored),
+            8 => Ok(Self::Deflated),
+            _ => Err(NdkError::InvalidZip(format!(
+                "unsupported compression method `{method}`"
+            ))),
+        }
+    }
+}
+
+/// Modification time of a zip entry, in MS-DOS format
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DosDateTime {
+    pub time: u16,
+    pub date: u16,
+}
+
+impl DosDateTime {
+    /// The earliest representable timestamp, `1980-01-01 00:00:00`
+    pub const EPOCH: Self = Self {
+        time: 0,
+        date: (1 << 5) | 1,
+    };
+
+    /// Converts a UTC timestamp, clamping it to the range representable by MS-DOS
+    /// timestamps (1980 to 2107)
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self::from_unix_timestamp(secs)
+    }
+
+    /// Reads the timestamp from the [`SOURCE_DATE_EPOCH`] environment variable used
+    /// by reproducible builds, falling back to [`DosDateTime::EPOCH`] when it is unset.
+    ///
+    /// [`SOURCE_DATE_EPOCH`]: https://reproducible-builds.org/specs/source-date-epoch/
+    pub fn from_source_date_epoch() -> Result<Self, NdkError> {
+        match std::env::var("SOURCE_DATE_EPOCH") {
+            Ok(epoch) => epoch
+                .trim()
+                .parse()
+                .map(Self::from_unix_timestamp)
+                .map_err(|_| NdkError::InvalidSourceDateEpoch(epoch)),
+            Err(_) => Ok(Self::EPOCH),
+        }
+    }
+
+    pub fn from_unix_timestamp(secs: u64) -> Self {
+        let days = (secs / 86400) as i64;
+        let secs_of_day = secs % 86400;
+        let (year, month, day) = civil_from_days(days);
+        if year < 1980 {
+            return Self::EPOCH;
+        }
+        if year > 2107 {
+            return Self {
+                time: (23 << 11) | (59 << 5) | (58 / 2),
+                date: (127 << 9) | (12 << 5) | 31,
+            };
+        }
+        let hour = (secs_of_day / 3600) as u16;
+        let minute = (secs_of_day % 3600 / 60) as u16;
+        let second = (secs_of_day % 60) as u16;
+        Self {
+            time: (hour << 11) | (minute << 5) | (second / 2),
+            date: (((year - 1980) as u16) << 9) | ((month as u16) << 5) | day as u16,
+        }
+    }
+}
+
+/// Converts days since the Unix epoch to a `(year, month, day)` civil date.
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// An entry parsed from the central directory of a [`ZipArchive`]
+#[derive(Clone, Debug)]
+pub struct ZipEntry {
+    pub name: String,
+    pub compression: Compression,
+    pub modified: DosDateTime,
+    pub crc32: u32,
+    pub compressed_size: u32,
+    pub uncompressed_size: u32,
+    pub external_attributes: u32,
+    /// Offset of the (possibly compressed) entry data within the archive
+    data_offset: usize,
+}
+
+/// Read-only view of a zip archive held in memory
+pub struct ZipArchive<'a> {
+    data: &'a [u8],
+    entries: Vec<ZipEntry>,
+    central_directory_offset: usize,
+    end_of_central_directory_offset: usize,
+}
+
+impl<'a> ZipArchive<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, NdkError> {
+        let eocd = find_end_of_central_directory(data)?;
+        let entry_count = read_u16(data, eocd + 10)? as usize;
+        let cd_offset = read_u32(data, eocd + 16)? as usize;
+
+        let mut entries = Vec::with_capacity(entry_count);
+        let mut offset = cd_offset;
+        for _ in 0..entry_count {
+            if read_u32(data, offset)? != CENTRAL_DIRECTORY_SIGNATURE {
+                return Err(NdkError::InvalidZip(
+                    "invalid central directory header signature".to_string(),
+                ));
+            }
+            let compression = Compression::from_method(read_u16(data, offset + 10)?)?;
+            let modified = DosDateTime {
+                time: read_u16(data, offset + 12)?,
+                date: read_u16(data, offset + 14)?,
+            };
+            let crc32 = read_u32(data, offset + 16)?;
+            let compressed_size = read_u32(data, offset + 20)?;
+            let uncompressed_size = read_u32(data, offset + 24)?;
+            let name_len = read_u16(data, offset + 28)? as usize;
+            let extra_len = read_u16(data, offset + 30)? as usize;
+            let comment_len = read_u16(data, offset + 32)? as usize;
+            let external_attributes = read_u32(data, offset + 38)?;
+            let local_header_offset = read_u32(data, offset + 42)? as usize;
+            let name_start = offset + CENTRAL_DIRECTORY_HEADER_LEN;
+            let name = std::str::from_utf8(slice(data, name_start, name_len)?)
+                .map_err(|_| NdkError::InvalidZip("entry name is not UTF-8".to_string()))?
+                .to_string();
+
+            if read_u32(data, local_header_offset)? != LOCAL_FILE_HEADER_SIGNATURE {
+                return Err(NdkError::InvalidZip(format!(
+                    "invalid local file header signature for `{name}`"
+                )));
+            }
+            let local_name_len = read_u16(data, local_header_offset + 26)? as usize;
+            let local_extra_len = read_u16(data, local_header_offset + 28)? as usize;
+            let data_offset =
+                local_header_offset + LOCAL_FILE_HEADER_LEN + local_name_len + local_extra_len;
+            slice(data, data_offset, compressed_size as usize)?;
+
+            entries.push(ZipEntry {
+                name,
+                compression,
+                modified,
+                crc32,
+                compressed_size,
+                uncompressed_size,
+                external_attributes,
+                data_offset,
+            });
+            offset = name_start + name_len + extra_len + comment_len;
+        }
+
+        Ok(Self {
+            data,
+            entries,
+            central_directory_offset: cd_offset,
+            end_of_central_directory_offset: eocd,
+        })
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    pub fn central_directory_offset(&self) -> usize {
+        self.central_directory_offset
+    }
+
+    pub fn end_of_central_directory_offset(&self) -> usize {
+        self.end_of_central_directory_offset
+    }
+
+    /// Returns the decompressed contents of `entry`
+    pub fn read(&self, entry: &ZipEntry) -> Result<Vec<u8>, NdkError> {
+        let raw = self.raw_data(entry);
+        match entry.compression {
+            Compression::Stored => Ok(raw.to_vec()),
+            Compression::Deflated => {
+                let mut data = Vec::with_capacity(entry.uncompressed_size as usize);
+                flate2::read::DeflateDecoder::new(raw).read_to_end(&mut data)?;
+                Ok(data)
+            }
+        }
+    }
+
+    /// Returns the data of `entry` as stored in the archive, without decompressing it
+    pub fn raw_data(&self, entry: &ZipEntry) -> &'a [u8] {
+        &self.data[entry.data_offset..entry.data_offset + entry.compressed_size as usize]
+    }
+}
+
+fn find_end_of_central_directory(data: &[u8]) -> Result<usize, NdkError> {
+    if data.len() < END_OF_CENTRAL_DIRECTORY_LEN {
+        return Err(NdkError::InvalidZip("archive is too small".to_string()));
+    }
+    // The record is followed by a comment of at most `u16::MAX` bytes
+    let last = data.len() - END_OF_CENTRAL_DIRECTORY_LEN;
+    let first = last.saturating_sub(u16::MAX as usize);
+    (first..=last)
+        .rev()
+        .find(|&offset| read_u32(data, offset).ok() == Some(END_OF_CENTRAL_DIRECTORY_SIGNATURE))
+        .ok_or_else(|| NdkError::InvalidZip("no end of central directory record".to_string()))
+}
+
+fn slice(data: &[u8], offset: usize, len: usize) -> Result<&[u8], NdkError> {
+    data.get(offset..offset + len)
+        .ok_or_else(|| NdkError::InvalidZip("unexpected end of archive".to_string()))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, NdkError> {
+    Ok(u16::from_le_bytes(
+        slice(data, offset, 2)?.try_into().unwrap(),
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, NdkError> {
+    Ok(u32::from_le_bytes(
+        slice(data, offset, 4)?.try_into().unwrap(),
+    ))
+}
+
+struct CentralDirectoryEntry {
+    name: String,
+    compression: Compression,
+    modified: DosDateTime,
+    crc32: u32,
+    compressed_size: u32,
+    uncompressed_size: u32,
+    external_attributes: u32,
+    local_header_offset: u32,
+}
+
+/// Streaming zip writer that aligns stored entries as they are written
+pub struct ZipWriter<W: Write> {
+    inner: W,
+    offset: u64,
+    entries: Vec<CentralDirectoryEntry>,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            offset: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Compresses `data` with `compression` and writes it as a new entry. The data of
+    /// stored entries is aligned to `alignment` bytes.
+    pub fn add_entry(
+        &mut self,
+        name: &str,
+        data: &[u8],
+        compression: Compression,
+        modified: DosDateTime,
+        alignment: u16,
+    ) -> Result<(), NdkError> {
+        let crc32 = crc32fast::hash(data);
+        let compressed;
+        let raw = match compression {
+            Compression::Stored => data,
+            Compression::Deflated => {
+                let mut encoder =
+                    flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                compressed = encoder.finish()?;
+                &compressed
+            }
+        };
+        self.write_entry(
+            CentralDirectoryEntry {
+                name: name.to_string(),
+                compression,
+                modified,
+                crc32,
+                compressed_size: to_u32(raw.len())?,
+                uncompressed_size: to_u32(data.len())?,
+                external_attributes: 0,
+                local_header_offset: 0,
+            },
+            raw,
+            alignment,
+        )
+    }
+
+    /// Copies an entry from another archive without recompressing it. The data of
+    /// stored entries is (re)aligned to `alignment` bytes.
+    pub fn copy_entry(
+        &mut self,
+        entry: &ZipEntry,
+        raw: &[u8],
+        alignment: u16,
+    ) -> Result<(), NdkError> {
+        self.copy_entry_as(&entry.name, entry, raw, alignment)
+    }
+
+    /// Same as [`ZipWriter::copy_entry`], but stores the entry under a different `name`
+    pub fn copy_entry_as(
+        &mut self,
+        name: &str,
+        entry: &ZipEntry,
+        raw: &[u8],
+        alignment: u16,
+    ) -> Result<(), NdkError> {
+        self.write_entry(
+            CentralDirectoryEntry {
+                name: name.to_string(),
+                compression: entry.compression,
+                modified: entry.modified,
+                crc32: entry.crc32,
+                compressed_size: entry.compressed_size,
+                uncompressed_size: entry.uncompressed_size,
+                external_attributes: entry.external_attributes,
+                local_header_offset: 0,
+            },
+            raw,
+            alignment,
+        )
+    }
+
+    fn write_entry(
+        &mut self,
+        mut entry: CentralDirectoryEntry,
+        raw: &[u8],
+        alignment: u16,
+    ) -> Result<(), NdkError> {
+        entry.local_header_offset = to_u32(self.offset)?;
+
+        let name = entry.name.as_bytes();
+        let mut extra = Vec::new();
+        if entry.compression == Compression::Stored && alignment > 1 {
+            let data_offset = self.offset
+                + (LOCAL_FILE_HEADER_LEN + name.len() + ALIGNMENT_EXTRA_FIELD_LEN) as u64;
+            let padding = (alignment as u64 - data_offset % alignment as u64) % alignment as u64;
+            extra.extend_from_slice(&ALIGNMENT_EXTRA_FIELD_ID.to_le_bytes());
+            extra.extend_from_slice(&(2 + padding as u16).to_le_bytes());
+            extra.extend_from_slice(&alignment.to_le_bytes());
+            extra.resize(ALIGNMENT_EXTRA_FIELD_LEN + padding as usize, 0);
+        }
+
+        let mut header = Vec::with_capacity(LOCAL_FILE_HEADER_LEN + name.len() + extra.len());
+        header.extend_from_slice(&LOCAL_FILE_HEADER_SIGNATURE.to_le_bytes());
+        header.extend_from_slice(&20u16.to_le_bytes()); // version needed to extract
+        header.extend_from_slice(&0u16.to_le_bytes()); // general purpose flags
+        header.extend_from_slice(&entry.compression.method().to_le_bytes());
+        header.extend_from_slice(&entry.modified.time.to_le_bytes());
+        header.extend_from_slice(&entry.modified.date.to_le_bytes());
+        header.extend_from_slice(&entry.crc32.to_le_bytes());
+        header.extend_from_slice(&entry.compressed_size.to_le_bytes());
+        header.extend_from_slice(&entry.uncompressed_size.to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        header.extend_from_slice(name);
+        header.extend_from_slice(&extra);
+
+        self.inner.write_all(&header)?;
+        self.inner.write_all(raw)?;
+        self.offset += (header.len() + raw.len()) as u64;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Writes the central directory and returns the underlying writer
+    pub fn finish(mut self) -> Result<W, NdkError> {
+        let cd_offset = to_u32(self.offset)?;
+        let mut cd = Vec::new();
+        for entry in &self.entries {
+            let name = entry.name.as_bytes();
+            cd.extend_from_slice(&CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+            cd.extend_from_slice(&20u16.to_le_bytes()); // version made by
+            cd.extend_from_slice(&20u16.to_le_bytes()); // version needed to extract
+            cd.extend_from_slice(&0u16.to_le_bytes()); // general purpose flags
+            cd.extend_from_slice(&entry.compression.method().to_le_bytes());
+            cd.extend_from_slice(&entry.modified.time.to_le_bytes());
+            cd.extend_from_slice(&entry.modified.date.to_le_bytes());
+            cd.extend_from_slice(&entry.crc32.to_le_bytes());
+            cd.extend_from_slice(&entry.compressed_size.to_le_bytes());
+            cd.extend_from_slice(&entry.uncompressed_size.to_le_bytes());
+            cd.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            cd.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+            cd.extend_from_slice(&0u16.to_le_bytes()); // comment length
+            cd.extend_from_slice(&0u16.to_le_bytes()); // disk number start
+            cd.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+            cd.extend_from_slice(&entry.external_attributes.to_le_bytes());
+            cd.extend_from_slice(&entry.local_header_offset.to_le_bytes());
+            cd.extend_from_slice(name);
+        }
+
+        let entry_count: u16 = self
+            .entries
+            .len()
+            .try_into()
+            .map_err(|_| NdkError::InvalidZip("too many entries".to_string()))?;
+        let mut eocd = Vec::with_capacity(END_OF_CENTRAL_DIRECTORY_LEN);
+        eocd.extend_from_slice(&END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+        eocd.extend_from_slice(&0u16.to_le_bytes()); // number of this disk
+        eocd.extend_from_slice(&0u16.to_le_bytes()); // disk where central directory starts
+        eocd.extend_from_slice(&entry_count.to_le_bytes());
+        eocd.extend_from_slice(&entry_count.to_le_bytes());
+        eocd.extend_from_slice(&to_u32(cd.len())?.to_le_bytes());
+        eocd.extend_from_slice(&cd_offset.to_le_bytes());
+        eocd.extend_from_slice(&0u16.to_le_bytes()); // comment length
+
+        self.inner.write_all(&cd)?;
+        self.inner.write_all(&eocd)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+fn to_u32<T: TryInto<u32>>(value: T) -> Result<u32, NdkError> {
+    value.try_into().map_err(|_| {
+        NdkError::InvalidZip("archive exceeds 4 GiB, zip64 is not supported".to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dos_date_time() {
+        assert_eq!(DosDateTime::from_unix_timestamp(0), DosDateTime::EPOCH);
+        // 2023-11-30 12:34:56
+        let t = DosDateTime::from_unix_timestamp(1701347696);
+        assert_eq!(t.date, (43 << 9) | (11 << 5) | 30);
+        assert_eq!(t.time, (12 << 11) | (34 << 5) | (56 / 2));
+    }
+
+    #[test]
+    fn source_date_epoch() {
+        // The only test that reads or writes `SOURCE_DATE_EPOCH`
+        std::env::remove_var("SOURCE_DATE_EPOCH");
+        assert_eq!(
+            DosDateTime::from_source_date_epoch().unwrap(),
+            DosDateTime::EPOCH
+        );
+        std::env::set_var("SOURCE_DATE_EPOCH", "1701347696\n");
+        assert_eq!(
+            DosDateTime::from_source_date_epoch().unwrap(),
+            DosDateTime::from_unix_timestamp(1701347696)
+        );
+        std::env::set_var("SOURCE_DATE_EPOCH", "2023-11-30");
+        assert!(matches!(
+            DosDateTime::from_source_date_epoch(),
+            Err(NdkError::InvalidSourceDateEpoch(epoch)) if epoch == "2023-11-30"
+        ));
+        std::env::remove_var("SOURCE_DATE_EPOCH");
+    }
+
+    #[test]
+    fn roundtrip_and_alignment() {
+        let mut writer = ZipWriter::new(Vec::new());
+        writer
+            .add_entry(
+                "AndroidManifest.xml",
+                b"<manifest/>",
+                Compression::Deflated,
+                DosDateTime::EPOCH,
+                DEFAULT_ALIGNMENT,
+            )
+            .unwrap();
+        writer
+            .add_entry(
+                "lib/arm64-v8a/libfoo.so",
+                &[0x7f; 100],
+                Compression::Stored,
+                DosDateTime::EPOCH,
+                PAGE_ALIGNMENT,
+            )
+            .unwrap();
+        writer
+            .add_entry(
+                "resources.arsc",
+                &[1, 2, 3],
+                Compression::Stored,
+                DosDateTime::EPOCH,
+                DEFAULT_ALIGNMENT,
+            )
+            .unwrap();
+        let data = writer.finish().unwrap();
+
+        let archive = ZipArchive::parse(&data).unwrap();
+        let entries = archive.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].compression, Compression::Deflated);
+        assert_eq!(entries[1].data_offset % PAGE_ALIGNMENT as usize, 0);
+        assert_eq!(archive.raw_data(&entries[1]), &[0x7f; 100]);
+        assert_eq!(entries[2].data_offset % DEFAULT_ALIGNMENT as usize, 0);
+        assert_eq!(archive.raw_data(&entries[2]), &[1, 2, 3]);
+
+        assert_eq!(archive.read(&entries[0]).unwrap(), b"<manifest/>");
+    }
+}